@@ -0,0 +1,166 @@
+use fse_trab_2::elevator::building::{Building, BUILDING_FILE};
+use fse_trab_2::uart::emulator::Esp32Emulator;
+use std::ffi::CStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::os::fd::FromRawFd;
+use std::os::unix::fs::symlink;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Opens a pseudo-terminal pair in raw mode, returning the master side and the path of the slave side
+fn open_pty() -> io::Result<(File, String)> {
+    unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+
+        if fd < 0 || libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut termios = std::mem::zeroed();
+
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        libc::cfmakeraw(&mut termios);
+
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut name = [0 as libc::c_char; 128];
+
+        if libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let path = CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+
+        Ok((File::from_raw_fd(fd), path))
+    }
+}
+
+fn print_help() {
+    println!("Commands:");
+    println!("  press <register>          press a button, e.g. press 0x07");
+    println!("  release <register>        release a button");
+    println!("  encoder <car> <value>     set the encoder position, e.g. encoder 1 5000");
+    println!("  status                    print pwm and temperature received");
+}
+
+fn run_command(
+    emulator: &Mutex<Esp32Emulator>,
+    building: &Building,
+    line: &str,
+) -> Result<(), String> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let mut emulator = emulator.lock().unwrap();
+
+    let parse_register = |value: &str| {
+        u8::from_str_radix(value.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Invalid register: {}", value))
+    };
+
+    // Cars are numbered from 1 like on the panel
+    let parse_encoder = |value: &str| {
+        value
+            .parse::<usize>()
+            .ok()
+            .and_then(|car| building.cars.get(car.checked_sub(1)?))
+            .map(|car| car.encoder)
+            .ok_or_else(|| format!("Invalid encoder: {}", value))
+    };
+
+    match args.as_slice() {
+        ["press", register] | ["release", register] => {
+            let register = parse_register(register)?;
+
+            if !emulator.set_register(register, args[0] == "press") {
+                return Err(format!("Unknown button register: {:X}", register));
+            }
+        }
+        ["encoder", encoder, value] => {
+            let encoder = parse_encoder(encoder)?;
+            let value = value
+                .parse()
+                .map_err(|_| format!("Invalid encoder value: {}", value))?;
+
+            emulator.set_encoder(encoder, value);
+        }
+        ["status"] => {
+            for elevator in building.elevators() {
+                println!(
+                    "Elevator {}: pwm {} temp {:.1}",
+                    elevator,
+                    emulator.pwm(building.car(elevator).encoder),
+                    emulator.temperature(elevator)
+                );
+            }
+        }
+        [] => {}
+        _ => print_help(),
+    }
+
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let (mut master, slave_path) = open_pty()?;
+
+    // Keeping the slave side open avoids EIO on the master while the controller is not connected
+    let _slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&slave_path)?;
+
+    let port = match std::env::args().nth(1) {
+        Some(link) => {
+            let _ = fs::remove_file(&link);
+            symlink(&slave_path, &link)?;
+            link
+        }
+        None => slave_path,
+    };
+
+    println!("ESP32 emulator listening on {}", port);
+    println!("Start the controller with ESP32_SERIAL_PORT={}", port);
+    print_help();
+
+    // Same registers as the controller started from this directory
+    let building = Building::load(BUILDING_FILE)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let blocks = building
+        .cars
+        .iter()
+        .map(|car| {
+            let (first, last) = car.block();
+            first.0..=last.0
+        })
+        .collect();
+
+    let emulator = Esp32Emulator::with_button_blocks(blocks)
+        .with_encoders(building.cars.iter().map(|car| car.encoder));
+    let emulator = Arc::new(Mutex::new(emulator));
+
+    {
+        let emulator = emulator.clone();
+
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if let Err(msg) = run_command(&emulator, &building, &line) {
+                    eprintln!("{}", msg);
+                }
+            }
+        });
+    }
+
+    let mut buffer = [0; 256];
+
+    loop {
+        let read = master.read(&mut buffer)?;
+        let response = emulator.lock().unwrap().receive(&buffer[..read]);
+
+        master.write_all(&response)?;
+    }
+}
@@ -1,17 +1,30 @@
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-#[repr(u8)]
-pub enum Elevator {
-    One = 0x00,
-    Two = 0x01,
+use std::fmt::{self, Display, Formatter};
+
+/// Car of the fleet counted from 0, in the order of the building topology
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Elevator(pub usize);
+
+impl Elevator {
+    pub fn index(self) -> usize {
+        self.0
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd)]
-pub enum Floor {
-    Ground,
-    First,
-    Second,
-    Third,
-    Undefined,
+impl Display for Elevator {
+    // Numbered from 1 like the panel and the display
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0 + 1)
+    }
+}
+
+/// Landing counted from the lowest one, named and wired by the building topology
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
+pub struct Floor(pub usize);
+
+impl Floor {
+    pub fn index(self) -> usize {
+        self.0
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -20,3 +33,29 @@ pub enum Direction {
     Down,
     Stop,
 }
+
+impl Direction {
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Stop => Direction::Stop,
+        }
+    }
+}
+
+/// Serde format of a duration as a number of seconds, e.g. `dwell = 2.5` in building.toml
+pub mod seconds {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let seconds = f64::deserialize(deserializer)?;
+
+        Duration::try_from_secs_f64(seconds).map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,436 @@
+use crate::common::{
+    Direction::{Down, Stop, Up},
+    Elevator, Floor,
+};
+use crate::elevator::calls::Call;
+use crate::elevator::doors::{DoorCommand, DoorConfig};
+use crate::elevator::emergency_stop::EmergencyConfig;
+use crate::elevator::trajectory::TrajectoryLimits;
+use crate::error::{Error, Result};
+use crate::gpio::pid::PidGains;
+use crate::uart::esp32::{Button, Encoder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+
+/// Topology read by the controller and the emulator from their working directory
+pub const BUILDING_FILE: &str = "building.toml";
+
+// The floor is drawn with a 10x20 font in the corner of each half of the display
+const MAX_NAME_LEN: usize = 2;
+
+// Cars driven by the controller, each one gets a column of the 128 px wide display
+const MAX_CARS: usize = 4;
+
+// Registers of a car are read and written in one transaction, whose length field is a byte
+const MAX_BLOCK_LEN: usize = 255;
+
+/// Landing served by the cars.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Landing {
+    /// Shown on the display and the calibration notices, e.g. "T", "S1" or "3"
+    pub name: String,
+}
+
+/// Registers of the buttons serving one landing in the block of a car.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LandingButtons {
+    /// Hall call going up, missing on the top landing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up: Option<Button>,
+    /// Hall call going down, missing on the bottom landing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub down: Option<Button>,
+    /// Button inside the car
+    pub call: Button,
+}
+
+impl LandingButtons {
+    fn registers(&self) -> impl Iterator<Item = Button> {
+        self.up.into_iter().chain(self.down).chain([self.call])
+    }
+}
+
+/// BCM outputs of the H-bridge of a car.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MotorPins {
+    pub dir1: u8,
+    pub dir2: u8,
+    /// PWM enable
+    pub potm: u8,
+}
+
+/// Wiring of one car, with a sensor and a set of buttons per landing from the lowest one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CarWiring {
+    pub encoder: Encoder,
+    pub motor: MotorPins,
+    /// I2C address of the BME280 of the car, no temperature is reported without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermometer: Option<u8>,
+    /// BCM input of each floor sensor
+    pub sensor_pins: Vec<u8>,
+    pub buttons: Vec<LandingButtons>,
+    pub emergency: Button,
+    /// Door-open button inside the car, missing on the lab rig
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub door_open: Option<Button>,
+    /// Door-close button inside the car, missing on the lab rig
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub door_close: Option<Button>,
+    /// Door timing, the lab rig one when missing
+    #[serde(default)]
+    pub doors: DoorConfig,
+    /// How the car stops on its emergency button, a hard stop when missing
+    #[serde(default)]
+    pub emergency_stop: EmergencyConfig,
+    /// Speed, acceleration and jerk of the trips
+    #[serde(default)]
+    pub trajectory: TrajectoryLimits,
+    /// Gains of the position controller, the ones tuned on the lab rig when missing
+    #[serde(default)]
+    pub pid: PidGains,
+}
+
+impl CarWiring {
+    fn registers(&self) -> impl Iterator<Item = Button> + '_ {
+        self.buttons
+            .iter()
+            .flat_map(LandingButtons::registers)
+            .chain([self.emergency])
+            .chain(self.door_open)
+            .chain(self.door_close)
+    }
+
+    /// Command of a door button of this car, None for any other register
+    pub fn door_command_of(&self, button: Button) -> Option<DoorCommand> {
+        if self.door_open == Some(button) {
+            Some(DoorCommand::Open)
+        } else if self.door_close == Some(button) {
+            Some(DoorCommand::Close)
+        } else {
+            None
+        }
+    }
+
+    /// First and last register of the car, read and written in a single transaction
+    pub fn block(&self) -> (Button, Button) {
+        let first = self.registers().min().unwrap_or(self.emergency);
+        let last = self.registers().max().unwrap_or(self.emergency);
+
+        (first, last)
+    }
+
+    /// Call registered by a floor button of this car, None for the emergency and unknown registers
+    pub fn call_of(&self, button: Button) -> Option<Call> {
+        self.floors().find_map(|(floor, buttons)| {
+            if buttons.up == Some(button) {
+                Some(Call::Hall(floor, Up))
+            } else if buttons.down == Some(button) {
+                Some(Call::Hall(floor, Down))
+            } else if buttons.call == button {
+                Some(Call::Car(floor))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn floors(&self) -> impl Iterator<Item = (Floor, &LandingButtons)> {
+        self.buttons
+            .iter()
+            .enumerate()
+            .map(|(index, buttons)| (Floor(index), buttons))
+    }
+
+    /// Button whose lamp is lit by the call, None when the landing has no such button
+    pub fn button_of(&self, call: Call) -> Option<Button> {
+        let buttons = self.buttons.get(call.floor().index())?;
+
+        match call {
+            Call::Hall(_, Up) => buttons.up,
+            Call::Hall(_, Down) => buttons.down,
+            Call::Hall(_, Stop) => None,
+            Call::Car(_) => Some(buttons.call),
+        }
+    }
+
+    /// Lamp states of the whole block with only the given buttons lit
+    pub fn lamps(&self, lit: &[Button]) -> Vec<bool> {
+        let (first, last) = self.block();
+
+        (first.0..=last.0)
+            .map(|register| lit.contains(&Button(register)))
+            .collect()
+    }
+}
+
+/// Landings of the building and how each car is wired to them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Building {
+    /// From the lowest landing up
+    pub landings: Vec<Landing>,
+    /// Elevator 1 first
+    pub cars: Vec<CarWiring>,
+    /// Hall calls from any panel are dispatched to the car arriving first instead of the car of the panel
+    #[serde(default)]
+    pub group_control: bool,
+}
+
+impl Default for Building {
+    // The lab rig: ground plus three floors and two cars, the second car's registers offset by 0xA0
+    fn default() -> Self {
+        let car = |encoder: u8, motor: MotorPins, thermometer: u8, sensor_pins: [u8; 4]| {
+            let offset = encoder * 0xA0;
+            let register = |address: u8| Button(offset + address);
+
+            CarWiring {
+                encoder: Encoder(encoder),
+                motor,
+                thermometer: Some(thermometer),
+                sensor_pins: sensor_pins.to_vec(),
+                buttons: vec![
+                    LandingButtons {
+                        up: Some(register(0x00)),
+                        down: None,
+                        call: register(0x07),
+                    },
+                    LandingButtons {
+                        up: Some(register(0x01)),
+                        down: Some(register(0x02)),
+                        call: register(0x08),
+                    },
+                    LandingButtons {
+                        up: Some(register(0x03)),
+                        down: Some(register(0x04)),
+                        call: register(0x09),
+                    },
+                    LandingButtons {
+                        up: None,
+                        down: Some(register(0x05)),
+                        call: register(0x0A),
+                    },
+                ],
+                emergency: register(0x06),
+                door_open: None,
+                door_close: None,
+                doors: DoorConfig::default(),
+                emergency_stop: EmergencyConfig::default(),
+                trajectory: TrajectoryLimits::default(),
+                pid: PidGains::default(),
+            }
+        };
+
+        Building {
+            landings: ["T", "1", "2", "3"]
+                .map(|name| Landing {
+                    name: name.to_string(),
+                })
+                .to_vec(),
+            cars: vec![
+                car(
+                    0,
+                    MotorPins {
+                        dir1: 20,
+                        dir2: 21,
+                        potm: 12,
+                    },
+                    0x76,
+                    [18, 23, 24, 25],
+                ),
+                car(
+                    1,
+                    MotorPins {
+                        dir1: 19,
+                        dir2: 26,
+                        potm: 13,
+                    },
+                    0x77,
+                    [17, 27, 22, 6],
+                ),
+            ],
+            group_control: false,
+        }
+    }
+}
+
+impl Building {
+    /// Reads the topology from a TOML file, the lab rig when the file does not exist
+    pub fn load(path: &str) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Building::parse(&text),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Building::default()),
+            Err(e) => Err(Error::Building(format!("{}: {}", path, e))),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let building: Building =
+            toml::from_str(text).map_err(|e| Error::Building(e.to_string()))?;
+
+        building.validate().map_err(Error::Building)?;
+
+        Ok(building)
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.landings.len() < 2 {
+            return Err("a building needs at least two landings".to_string());
+        }
+
+        if let Some(landing) = self
+            .landings
+            .iter()
+            .find(|landing| landing.name.is_empty() || landing.name.len() > MAX_NAME_LEN)
+        {
+            return Err(format!(
+                "landing name {:?} must have 1 to {} characters",
+                landing.name, MAX_NAME_LEN
+            ));
+        }
+
+        if self.cars.is_empty() || self.cars.len() > MAX_CARS {
+            return Err(format!(
+                "expected 1 to {} cars, got {}",
+                MAX_CARS,
+                self.cars.len()
+            ));
+        }
+
+        let mut registers = HashSet::new();
+        let mut encoders = HashSet::new();
+        let mut pins = HashSet::new();
+
+        for (i, car) in self.cars.iter().enumerate() {
+            let name = format!("car {}", i + 1);
+
+            if !encoders.insert(car.encoder) {
+                return Err(format!("{} reuses encoder {}", name, car.encoder.0));
+            }
+
+            let motor = [car.motor.dir1, car.motor.dir2, car.motor.potm];
+
+            if let Some(pin) = motor
+                .iter()
+                .chain(&car.sensor_pins)
+                .find(|pin| !pins.insert(**pin))
+            {
+                return Err(format!("{} reuses pin {}", name, pin));
+            }
+
+            if car.sensor_pins.len() != self.landings.len() {
+                return Err(format!("{} needs one sensor pin per landing", name));
+            }
+
+            if car.buttons.len() != self.landings.len() {
+                return Err(format!("{} needs one set of buttons per landing", name));
+            }
+
+            if let Some(register) = car
+                .registers()
+                .find(|register| !registers.insert(*register))
+            {
+                return Err(format!("{} reuses register {:#04X}", name, register.0));
+            }
+
+            // Negative times are already refused when parsing
+            if car.doors.travel.is_zero() || car.doors.dwell.is_zero() {
+                return Err(format!("{} needs positive door timings", name));
+            }
+
+            let positive = |value: f64| value.is_finite() && value > 0.0;
+            let limits = &car.trajectory;
+
+            if ![
+                limits.full_speed,
+                limits.speed,
+                limits.acceleration,
+                limits.jerk,
+            ]
+            .into_iter()
+            .all(positive)
+            {
+                return Err(format!("{} needs positive trajectory limits", name));
+            }
+
+            if limits.speed > limits.full_speed {
+                return Err(format!("{} cruises faster than its full speed", name));
+            }
+
+            if !positive(car.emergency_stop.deceleration) {
+                return Err(format!("{} needs a positive emergency deceleration", name));
+            }
+
+            let pid = &car.pid;
+            let non_negative = |value: f64| value.is_finite() && value >= 0.0;
+
+            if ![pid.kp, pid.ki, pid.kd, pid.derivative_filter]
+                .into_iter()
+                .all(non_negative)
+            {
+                return Err(format!("{} needs non-negative controller gains", name));
+            }
+
+            let (first, last) = car.block();
+
+            if (last.0 - first.0) as usize + 1 > MAX_BLOCK_LEN {
+                return Err(format!(
+                    "{} spans registers {:#04X} to {:#04X}, more than {}",
+                    name, first.0, last.0, MAX_BLOCK_LEN
+                ));
+            }
+        }
+
+        // Writing the lamps of a car rewrites its whole block, which must not reach another car
+        for (i, car) in self.cars.iter().enumerate() {
+            let (first, last) = car.block();
+
+            if let Some(j) = self.cars[..i].iter().position(|other| {
+                let (other_first, other_last) = other.block();
+                first <= other_last && other_first <= last
+            }) {
+                return Err(format!(
+                    "the registers of car {} overlap those of car {}",
+                    i + 1,
+                    j + 1
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn car(&self, elevator: Elevator) -> &CarWiring {
+        &self.cars[elevator.index()]
+    }
+
+    /// Buttons lit by a call given to the car, under group control a hall call lights every panel
+    pub fn buttons_of(&self, elevator: Elevator, call: Call) -> Vec<Button> {
+        match call {
+            Call::Hall(..) if self.group_control => self
+                .cars
+                .iter()
+                .filter_map(|car| car.button_of(call))
+                .collect(),
+            _ => self.car(elevator).button_of(call).into_iter().collect(),
+        }
+    }
+
+    /// Every car of the fleet
+    pub fn elevators(&self) -> impl Iterator<Item = Elevator> {
+        (0..self.cars.len()).map(Elevator)
+    }
+
+    /// Every landing from the lowest one
+    pub fn floors(&self) -> impl Iterator<Item = Floor> {
+        (0..self.landings.len()).map(Floor)
+    }
+
+    /// Name of the landing, "?" while the car position is unknown
+    pub fn name(&self, floor: Option<Floor>) -> &str {
+        floor
+            .and_then(|floor| self.landings.get(floor.index()))
+            .map_or("?", |landing| &landing.name)
+    }
+}
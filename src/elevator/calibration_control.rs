@@ -1,157 +1,303 @@
-use crate::common::Direction::{Down, Stop, Up};
-use crate::common::Floor;
-use crate::elevator::elevator_control::{ElevatorState, FloorsPosition};
+use crate::common::Direction::{self, Down, Stop, Up};
+use crate::common::{Elevator, Floor};
+use crate::elevator::calibration_file::{self, ElevatorCalibration, SensorBand};
+use crate::elevator::drift::DriftCorrection;
+use crate::elevator::elevator_control::ElevatorState;
+use crate::elevator::landings::Landings;
+use crate::error::{CalibrationError, Error, Result};
+use crate::i2c::display::StatusDisplay;
 use crate::uart::esp32::Esp32;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use crate::uart::transport::Transport;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-const CALIBRATION_FILE: &str = "calibration.bin";
+pub const CALIBRATION_FILE: &str = "calibration.toml";
 
-pub fn read_calibration() -> Result<FloorsPosition, String> {
-    let mut file = File::open(CALIBRATION_FILE).map_err(|_| "Calibration file not found")?;
+// Raw layout written by earlier versions next to the calibration file, migrated on the first read
+const LEGACY_CALIBRATION_FILE: &str = "calibration.bin";
 
-    let mut floors_position = FloorsPosition::default();
+// Sensors are sampled this often while the car moves, short enough to catch a band at sweep speed
+const POLL_PERIOD: Duration = Duration::from_millis(10);
 
-    for i in 0..4 {
-        let mut buffer = [0; 4];
-        file.read_exact(&mut buffer)
-            .map_err(|e| format!("Invalid calibration file: {:?}", e))?;
-
-        let number = i32::from_le_bytes(buffer);
+/// Bounds of each calibration phase, past them the sweep stops the motor and fails.
+#[derive(Clone, Copy, Debug)]
+pub struct CalibrationLimits {
+    /// Longest time a single phase may take
+    pub phase_timeout: Duration,
+    /// Longest travel while descending to the bottom of the shaft (ticks)
+    pub homing_travel: i32,
+    /// Longest travel while rising to the next floor sensor (ticks)
+    pub floor_travel: i32,
+    /// Longest travel while crossing a sensor band (ticks)
+    pub band_travel: i32,
+}
 
-        match i {
-            0 => floors_position.ground = number,
-            1 => floors_position.first = number,
-            2 => floors_position.second = number,
-            3 => floors_position.third = number,
-            _ => unreachable!(),
+impl Default for CalibrationLimits {
+    // The lab shafts span ~25000 ticks with landings ~7500 ticks apart
+    fn default() -> Self {
+        CalibrationLimits {
+            phase_timeout: Duration::from_secs(60),
+            homing_travel: 40_000,
+            floor_travel: 12_000,
+            band_travel: 3_000,
         }
     }
+}
 
-    Ok(floors_position)
+fn read_file(path: &Path) -> std::result::Result<Vec<u8>, CalibrationError> {
+    fs::read(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => CalibrationError::NotFound,
+        _ => CalibrationError::File(e),
+    })
 }
 
-pub fn write_calibration(floors_position: &FloorsPosition) {
-    let mut file = File::create(CALIBRATION_FILE).unwrap();
+/// Calibration of each car indexed by elevator, None for a car missing from the file
+///
+/// Fails when the file was calibrated for another number of landings.
+pub fn read_calibration(
+    path: &Path,
+    landings: usize,
+    cars: usize,
+) -> Result<Vec<Option<ElevatorCalibration>>> {
+    let legacy = path.with_file_name(LEGACY_CALIBRATION_FILE);
+
+    match read_file(path) {
+        Ok(bytes) => calibration_file::parse(&String::from_utf8_lossy(&bytes), landings, cars),
+        Err(CalibrationError::NotFound) => {
+            let calibrations =
+                calibration_file::parse_legacy(&read_file(&legacy)?, landings, cars)?;
 
-    for i in 0..4 {
-        let number = match i {
-            0 => floors_position.ground,
-            1 => floors_position.first,
-            2 => floors_position.second,
-            3 => floors_position.third,
-            _ => unreachable!(),
-        };
+            let migrated: Vec<_> = calibrations.iter().flatten().cloned().collect();
+            write_calibration(path, &migrated)?;
 
-        file.write_all(&number.to_le_bytes()).unwrap();
+            println!("Migrated {} to {}", legacy.display(), path.display());
+
+            Ok(calibrations)
+        }
+        Err(e) => Err(e.into()),
     }
 }
 
-pub fn start(
-    esp32: Arc<Mutex<Esp32>>,
-    elevator: Arc<Mutex<ElevatorState>>,
-    floors_range: Arc<RwLock<FloorsPosition>>,
-) {
-    println!("Starting elevator calibration, do not close the program.");
+/// Replaces the file through a rename, so a crash while writing never leaves it truncated
+pub fn write_calibration(path: &Path, calibrations: &[ElevatorCalibration]) -> Result<()> {
+    let text = calibration_file::render(calibrations)?;
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
 
-    let mut esp32 = esp32.lock().unwrap();
-    let mut elevator = elevator.lock().unwrap();
-    let mut floors_range = floors_range.write().unwrap();
+    let mut file = File::create(&temporary).map_err(CalibrationError::File)?;
 
-    // First, we need to move the elevator to the lowest point
-    elevator.engine_control.set_direction(Down);
-    elevator.engine_control.set_potency(1.0);
+    file.write_all(text.as_bytes())
+        .and_then(|_| file.sync_all())
+        .map_err(CalibrationError::File)?;
 
-    loop {
-        let current_position = esp32.get_encoder_value(elevator.encoder);
+    fs::rename(&temporary, path).map_err(CalibrationError::File)?;
 
-        if current_position <= 0 {
-            break;
-        }
+    Ok(())
+}
 
-        thread::sleep(Duration::from_millis(100));
+// Progress is informative only, a display failure must not abort the sweep
+fn report(display: &Arc<Mutex<impl StatusDisplay>>, elevator: Elevator, notice: &str) {
+    if let Err(e) = display.lock().unwrap().update_notice(elevator, notice) {
+        eprintln!("Couldn't show calibration progress: {}", e);
     }
+}
+
+pub fn start<T: Transport, D: StatusDisplay>(
+    esp32: Arc<Mutex<Esp32<T>>>,
+    display: Arc<Mutex<D>>,
+    elevator: Arc<Mutex<ElevatorState>>,
+    limits: CalibrationLimits,
+    abort: &AtomicBool,
+) -> Result<ElevatorCalibration> {
+    // Only the car is held for the sweep, the board is shared with the cars still serving
+    let mut elevator = elevator.lock().unwrap();
 
-    elevator.engine_control.set_direction(Stop);
-    elevator.engine_control.set_potency(0.0);
+    if !elevator.doors.is_closed() {
+        return Err(Error::Interlock(format!(
+            "elevator {} can't be calibrated with the doors {:?}",
+            elevator.elevator,
+            elevator.doors.state()
+        )));
+    }
 
-    wait_for_floor_calibration(
-        &mut esp32,
-        &mut elevator,
-        Floor::Ground,
-        &mut floors_range.ground,
+    println!(
+        "Starting calibration of elevator {}, do not close the program.",
+        elevator.elevator
     );
 
-    wait_for_floor_calibration(
-        &mut esp32,
-        &mut elevator,
-        Floor::First,
-        &mut floors_range.first,
-    );
+    let mut sweep = Sweep {
+        esp32: &esp32,
+        display: &display,
+        elevator: &mut elevator,
+        limits,
+        abort,
+    };
 
-    wait_for_floor_calibration(
-        &mut esp32,
-        &mut elevator,
-        Floor::Second,
-        &mut floors_range.second,
-    );
+    let result = sweep.run();
 
-    wait_for_floor_calibration(
-        &mut esp32,
-        &mut elevator,
-        Floor::Third,
-        &mut floors_range.third,
-    );
+    report(&display, elevator.elevator, "");
+
+    match result {
+        Ok(bands) => {
+            let calibration = ElevatorCalibration {
+                elevator: elevator.elevator.index() as u8 + 1,
+                calibrated_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs()),
+                positions: bands.iter().map(SensorBand::center).collect(),
+                bands: Some(bands),
+            };
+
+            // A sensor seen twice or out of order gives bands no trip could level with
+            let building = &elevator.building;
+
+            if let Err(e) = calibration.validate(building.landings.len(), building.cars.len()) {
+                eprintln!(
+                    "Elevator {} calibration rejected, the previous one is kept.",
+                    elevator.elevator
+                );
+
+                return Err(e);
+            }
+
+            elevator.landings = Landings::from_calibration(&calibration);
+            elevator.drift = DriftCorrection::new();
+            println!("Elevator {} calibration finished.", elevator.elevator);
+
+            Ok(calibration)
+        }
+        Err(e) => {
+            // Never leave the motor running after a failed calibration
+            let _ = elevator.engine_control.stop();
+
+            Err(e)
+        }
+    }
+}
 
-    println!("Elevator calibration finished.");
+struct Sweep<'a, 'b, T: Transport, D: StatusDisplay> {
+    esp32: &'a Arc<Mutex<Esp32<T>>>,
+    display: &'a Arc<Mutex<D>>,
+    elevator: &'a mut MutexGuard<'b, ElevatorState>,
+    limits: CalibrationLimits,
+    abort: &'a AtomicBool,
 }
 
-fn wait_for_floor_calibration(
-    esp32: &mut MutexGuard<Esp32>,
-    elevator: &mut MutexGuard<ElevatorState>,
-    floor: Floor,
-    floor_range: &mut i32,
-) {
-    // Then, we can start rising the elevator until the sensor is triggered
-    elevator.engine_control.set_direction(Up);
-    elevator.engine_control.set_potency(0.10);
-
-    while match floor {
-        Floor::Ground => &elevator.sensors.ground_sensor_pin,
-        Floor::First => &elevator.sensors.first_sensor_pin,
-        Floor::Second => &elevator.sensors.second_sensor_pin,
-        Floor::Third => &elevator.sensors.third_sensor_pin,
-        Floor::Undefined => unreachable!(),
+impl<T: Transport, D: StatusDisplay> Sweep<'_, '_, T, D> {
+    fn run(&mut self) -> Result<Vec<SensorBand>> {
+        report(self.display, self.elevator.elevator, "CALIBRANDO...");
+
+        // First, we need to move the elevator to the lowest point
+        let travel = self.limits.homing_travel;
+
+        self.drive_until(
+            Down,
+            1.0,
+            travel,
+            CalibrationError::Homing,
+            |_, position| position <= 0,
+        )?;
+
+        let building = self.elevator.building.clone();
+        let mut bands = Vec::with_capacity(building.landings.len());
+
+        for floor in building.floors() {
+            report(
+                self.display,
+                self.elevator.elevator,
+                &format!("CALIBRANDO {}", building.name(Some(floor))),
+            );
+
+            bands.push(self.wait_for_floor_calibration(floor)?);
+        }
+
+        Ok(bands)
     }
-    .is_low()
-    {}
 
-    elevator.engine_control.set_direction(Stop);
-    elevator.engine_control.set_potency(0.0);
+    fn wait_for_floor_calibration(&mut self, floor: Floor) -> Result<SensorBand> {
+        let sensor_error = |e| CalibrationError::Sensor(floor, e);
+
+        // Then, we can start rising the elevator until the sensor is triggered
+        let travel = self.limits.floor_travel;
+
+        self.drive_until(Up, 0.10, travel, sensor_error, |elevator, _| {
+            elevator.sensors.is_at(floor)
+        })?;
+
+        // At the sensor rising, we have the lower edge of the band
+        let lower = self.encoder()?;
 
-    // At the sensor rising, we can set the floor position
-    *floor_range = esp32.get_encoder_value(elevator.encoder);
+        // Then, we can start rising the elevator until the falling edge of the sensor
+        let travel = self.limits.band_travel;
 
-    // Then, we can start rising the elevator until the falling edge of the sensor
-    elevator.engine_control.set_direction(Up);
-    elevator.engine_control.set_potency(0.10);
+        self.drive_until(Up, 0.10, travel, sensor_error, |elevator, _| {
+            !elevator.sensors.is_at(floor)
+        })?;
 
-    while match floor {
-        Floor::Ground => &elevator.sensors.ground_sensor_pin,
-        Floor::First => &elevator.sensors.first_sensor_pin,
-        Floor::Second => &elevator.sensors.second_sensor_pin,
-        Floor::Third => &elevator.sensors.third_sensor_pin,
-        Floor::Undefined => unreachable!(),
+        // At the sensor falling edge, we have the upper edge, the floor position is its center
+        let upper = self.encoder()?;
+
+        Ok(SensorBand { lower, upper })
+    }
+
+    // Locks the board for this transaction only
+    fn encoder(&self) -> Result<i32> {
+        self.esp32
+            .lock()
+            .unwrap()
+            .get_encoder_value(self.elevator.encoder)
     }
-    .is_high()
-    {}
 
-    elevator.engine_control.set_direction(Stop);
-    elevator.engine_control.set_potency(0.0);
+    /// Drives the motor until `done` holds, then stops it
+    ///
+    /// Gives up with the error built by `stalled` when the phase outlasts its time or travels
+    /// more than `max_travel` ticks, and with `Aborted` as soon as the abort flag is raised or
+    /// the car enters an emergency.
+    fn drive_until(
+        &mut self,
+        direction: Direction,
+        potency: f64,
+        max_travel: i32,
+        stalled: impl Fn(String) -> CalibrationError,
+        done: impl Fn(&ElevatorState, i32) -> bool,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let origin = self.encoder()?;
+
+        self.elevator.engine_control.set_direction(direction)?;
+        self.elevator.engine_control.set_potency(potency)?;
+
+        loop {
+            let position = self.encoder()?;
+
+            if done(self.elevator, position) {
+                break;
+            }
+
+            // The emergency button of the car stops the sweep like the operator does
+            if self.abort.load(Relaxed) || self.elevator.emergency.load(Relaxed) {
+                return Err(CalibrationError::Aborted.into());
+            }
 
-    // At the sensor falling edge, we can set the floor position with a mean value
-    *floor_range = (*floor_range + esp32.get_encoder_value(elevator.encoder)) / 2;
+            if start.elapsed() > self.limits.phase_timeout {
+                return Err(stalled(format!("gave up after {:?}", start.elapsed())).into());
+            }
+
+            if (position - origin).abs() > max_travel {
+                return Err(stalled(format!("gave up after {} ticks", max_travel)).into());
+            }
+
+            thread::sleep(POLL_PERIOD);
+        }
+
+        self.elevator.engine_control.set_direction(Stop)?;
+        self.elevator.engine_control.set_potency(0.0)?;
+
+        Ok(())
+    }
 }
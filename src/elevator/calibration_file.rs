@@ -0,0 +1,221 @@
+use crate::error::{CalibrationError, Result};
+use crate::uart::crc;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+pub const VERSION: u32 = 2;
+
+// The lab shafts span ~25000 ticks, anything far outside a few taller rigs means a wrong or corrupted file
+const PLAUSIBLE_TICKS: RangeInclusive<i32> = 0..=100_000;
+
+// Four little-endian i32, ground to third, as written before the versioned format
+const LEGACY_RECORD_LEN: usize = 16;
+
+/// Encoder counts where a floor sensor turns on and off while the car rises.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SensorBand {
+    pub lower: i32,
+    pub upper: i32,
+}
+
+impl SensorBand {
+    pub fn center(&self) -> i32 {
+        (self.lower + self.upper) / 2
+    }
+}
+
+/// Result of calibrating one car.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElevatorCalibration {
+    /// Car number as printed on the panel, from 1
+    pub elevator: u8,
+    /// Unix time of the calibration sweep, 0 when migrated from a file without it
+    pub calibrated_at: u64,
+    /// Encoder count of each landing, from the lowest one
+    pub positions: Vec<i32>,
+    /// Sensor bands of each landing, missing when migrated from a file without them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bands: Option<Vec<SensorBand>>,
+}
+
+impl ElevatorCalibration {
+    /// Position of the car in a fleet of the given size, None when it is not part of it
+    pub fn index(&self, cars: usize) -> Option<usize> {
+        (self.elevator as usize)
+            .checked_sub(1)
+            .filter(|index| *index < cars)
+    }
+
+    /// Checks the positions are plausible and in order, and fit the sensor bands
+    pub fn validate(&self, landings: usize, cars: usize) -> Result<()> {
+        self.check(landings, cars).map_err(|e| invalid(e).into())
+    }
+
+    fn check(&self, landings: usize, cars: usize) -> std::result::Result<(), String> {
+        let name = format!("elevator {}", self.elevator);
+
+        if self.index(cars).is_none() {
+            return Err(format!("unknown {}", name));
+        }
+
+        let positions = &self.positions;
+
+        if positions.len() != landings {
+            return Err(format!(
+                "{} has {} positions for {} landings",
+                name,
+                positions.len(),
+                landings
+            ));
+        }
+
+        if let Some(tick) = positions
+            .iter()
+            .find(|tick| !PLAUSIBLE_TICKS.contains(tick))
+        {
+            return Err(format!("{} position {} out of range", name, tick));
+        }
+
+        if positions.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(format!("{} positions must increase: {:?}", name, positions));
+        }
+
+        if let Some(bands) = &self.bands {
+            if bands.len() != landings {
+                return Err(format!("{} has {} sensor bands", name, bands.len()));
+            }
+
+            for (band, position) in bands.iter().zip(positions) {
+                if band.lower > *position || *position > band.upper {
+                    return Err(format!("{} band {:?} misses {}", name, band, position));
+                }
+            }
+
+            if bands.windows(2).any(|pair| pair[0].upper >= pair[1].lower) {
+                return Err(format!("{} sensor bands overlap", name));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CalibrationFile {
+    version: u32,
+    /// CRC16 of the file written with a zero checksum
+    checksum: u16,
+    elevators: Vec<ElevatorCalibration>,
+}
+
+impl CalibrationFile {
+    fn compute_checksum(&self) -> Result<u16> {
+        checksum(&CalibrationFile {
+            version: self.version,
+            checksum: 0,
+            elevators: self.elevators.clone(),
+        })
+    }
+}
+
+/// Only the version is read first, the rest of the layout depends on it.
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+fn checksum(unsigned: &impl Serialize) -> Result<u16> {
+    let text = toml::to_string(unsigned).map_err(|e| invalid(e.to_string()))?;
+
+    Ok(crc::hash(text.as_bytes()))
+}
+
+fn verify(expected: u16, actual: u16) -> Result<()> {
+    if expected != actual {
+        return Err(invalid(format!("checksum {:X} != {:X}", expected, actual)).into());
+    }
+
+    Ok(())
+}
+
+fn invalid(message: String) -> CalibrationError {
+    CalibrationError::Invalid(message)
+}
+
+/// Renders the calibrations as a versioned TOML document with its checksum.
+pub fn render(calibrations: &[ElevatorCalibration]) -> Result<String> {
+    let mut file = CalibrationFile {
+        version: VERSION,
+        checksum: 0,
+        elevators: calibrations.to_vec(),
+    };
+
+    file.checksum = file.compute_checksum()?;
+
+    Ok(toml::to_string(&file).map_err(|e| invalid(e.to_string()))?)
+}
+
+/// Parses and validates a document written by [`render`], indexed by car.
+pub fn parse(text: &str, landings: usize, cars: usize) -> Result<Vec<Option<ElevatorCalibration>>> {
+    let versioned: Versioned = toml::from_str(text).map_err(|e| invalid(e.to_string()))?;
+
+    let elevators = match versioned.version {
+        VERSION => {
+            let file: CalibrationFile = toml::from_str(text).map_err(|e| invalid(e.to_string()))?;
+
+            verify(file.checksum, file.compute_checksum()?)?;
+
+            file.elevators
+        }
+        version => return Err(invalid(format!("unsupported version {}", version)).into()),
+    };
+
+    collect(elevators, landings, cars)
+}
+
+/// Reads the raw `calibration.bin` layout of the single elevator era, the four landings of elevator 1.
+pub fn parse_legacy(
+    bytes: &[u8],
+    landings: usize,
+    cars: usize,
+) -> Result<Vec<Option<ElevatorCalibration>>> {
+    if bytes.len() != LEGACY_RECORD_LEN {
+        return Err(invalid(format!("unexpected size of {} bytes", bytes.len())).into());
+    }
+
+    let positions = bytes
+        .chunks(4)
+        .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+
+    let calibration = ElevatorCalibration {
+        elevator: 1,
+        calibrated_at: 0,
+        positions,
+        bands: None,
+    };
+
+    collect(vec![calibration], landings, cars)
+}
+
+fn collect(
+    calibrations: Vec<ElevatorCalibration>,
+    landings: usize,
+    cars: usize,
+) -> Result<Vec<Option<ElevatorCalibration>>> {
+    let mut indexed = vec![None; cars];
+
+    for calibration in calibrations {
+        calibration.validate(landings, cars)?;
+
+        let slot = &mut indexed[calibration.index(cars).unwrap()];
+
+        if slot.is_some() {
+            return Err(invalid(format!("elevator {} appears twice", calibration.elevator)).into());
+        }
+
+        *slot = Some(calibration);
+    }
+
+    Ok(indexed)
+}
@@ -0,0 +1,194 @@
+use crate::common::{
+    Direction::{self, Down, Stop, Up},
+    Floor,
+};
+use crate::elevator::landings::Landings;
+
+/// Request registered by a button of the panel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Call {
+    /// Landing button, the passenger wants to leave in the given direction
+    Hall(Floor, Direction),
+    /// Button inside the car
+    Car(Floor),
+}
+
+impl Call {
+    pub fn floor(&self) -> Floor {
+        match *self {
+            Call::Hall(floor, _) | Call::Car(floor) => floor,
+        }
+    }
+
+    /// Whether a car leaving the landing in the direction takes the passenger
+    pub fn answers(&self, direction: Direction) -> bool {
+        match *self {
+            Call::Hall(_, wanted) => wanted == direction,
+            Call::Car(_) => true,
+        }
+    }
+}
+
+/// Pending calls of one car, served by collective control.
+///
+/// The car keeps its direction of travel while there are calls ahead of it and only reverses once
+/// none is left (LOOK). On the way it stops for car calls and for hall calls going its way, a hall
+/// call the other way waits until the car comes back.
+#[derive(Clone, Debug, Default)]
+pub struct CallSet {
+    // In registration order, which breaks ties between landings as far from an idle car
+    calls: Vec<Call>,
+}
+
+impl CallSet {
+    pub fn new() -> Self {
+        CallSet::default()
+    }
+
+    /// Registers a call, returns false when it was already pending
+    pub fn insert(&mut self, call: Call) -> bool {
+        if self.calls.contains(&call) {
+            return false;
+        }
+
+        self.calls.push(call);
+
+        true
+    }
+
+    pub fn contains(&self, call: Call) -> bool {
+        self.calls.contains(&call)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.calls.clear();
+    }
+
+    /// Removes and returns every pending call
+    pub fn take(&mut self) -> Vec<Call> {
+        std::mem::take(&mut self.calls)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Call> {
+        self.calls.iter()
+    }
+
+    /// Direction the car leaves the landing in after arriving in `arriving`, Stop when it turns idle
+    ///
+    /// The car keeps going while there are calls beyond the landing or a passenger waiting to go on.
+    pub fn departure(&self, floor: Floor, arriving: Direction) -> Direction {
+        let beyond = |direction| {
+            self.calls.iter().any(|call| match direction {
+                Up => call.floor() > floor,
+                Down => call.floor() < floor,
+                Stop => false,
+            })
+        };
+
+        let waiting = |direction| self.calls.contains(&Call::Hall(floor, direction));
+
+        let directions = match arriving {
+            Down => [Down, Up],
+            _ => [Up, Down],
+        };
+
+        directions
+            .into_iter()
+            .find(|direction| waiting(*direction) || beyond(*direction))
+            .unwrap_or(Stop)
+    }
+
+    /// Removes and returns the calls answered by a car stopped at the landing and leaving in `leaving`
+    ///
+    /// A car turning idle answers every call to the landing.
+    pub fn serve(&mut self, floor: Floor, leaving: Direction) -> Vec<Call> {
+        let (served, pending) = self
+            .calls
+            .iter()
+            .partition(|call| call.floor() == floor && (leaving == Stop || call.answers(leaving)));
+
+        self.calls = pending;
+
+        served
+    }
+
+    /// Landing to stop at next for a car at `current` travelling in `direction`
+    ///
+    /// A car whose position is unknown serves the oldest call first.
+    pub fn next(&self, current: Option<Floor>, direction: Direction) -> Option<Floor> {
+        let Some(current) = current else {
+            return self.calls.first().map(Call::floor);
+        };
+
+        match direction {
+            Up | Down => self
+                .ahead(current, direction)
+                .or_else(|| self.ahead(current, direction.opposite())),
+            Stop => self
+                .calls
+                .iter()
+                .map(Call::floor)
+                .min_by_key(|floor| floor.index().abs_diff(current.index())),
+        }
+    }
+
+    // First landing answered going that way, or else the farthest call ahead where the car turns around
+    fn ahead(&self, current: Floor, direction: Direction) -> Option<Floor> {
+        let ahead = || {
+            self.calls.iter().filter(move |call| match direction {
+                Up => call.floor() >= current,
+                _ => call.floor() <= current,
+            })
+        };
+
+        let answered = ahead()
+            .filter(|call| call.answers(direction))
+            .map(Call::floor);
+        let turning = ahead().map(Call::floor);
+
+        match direction {
+            Up => answered.min().or_else(|| turning.max()),
+            _ => answered.max().or_else(|| turning.min()),
+        }
+    }
+
+    /// First landing before `target` answered by a car travelling in `direction` that it can still stop at
+    ///
+    /// `horizon` is the nearest encoder count where the car could stop, the whole sensor band of the
+    /// landing must lie beyond it.
+    pub fn stop_before(
+        &self,
+        target: Floor,
+        direction: Direction,
+        landings: &Landings,
+        horizon: i32,
+    ) -> Option<Floor> {
+        let reachable = |stop: &Floor| {
+            let Some(band) = landings.band(*stop) else {
+                return false;
+            };
+
+            match direction {
+                Up => *stop < target && band.lower > horizon,
+                Down => *stop > target && band.upper < horizon,
+                Stop => false,
+            }
+        };
+
+        let stops = self
+            .calls
+            .iter()
+            .filter(|call| call.answers(direction))
+            .map(Call::floor)
+            .filter(reachable);
+
+        match direction {
+            Up => stops.min(),
+            _ => stops.max(),
+        }
+    }
+}
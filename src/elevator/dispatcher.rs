@@ -0,0 +1,135 @@
+use crate::common::{
+    Direction::{self, Down, Up},
+    Elevator, Floor,
+};
+use crate::elevator::calls::{Call, CallSet};
+use crate::elevator::doors::DoorState;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Rough travel time between adjacent landings of the lab rig, only compared between cars
+const FLOOR_TIME: Duration = Duration::from_secs(3);
+
+// Time lost by each stop on the way: leveling, the default door cycle and starting again
+const STOP_TIME: Duration = Duration::from_secs(4);
+
+/// Where a car is, published by its floor thread for the dispatcher which cannot take the car lock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CarStatus {
+    /// Nearest landing, None until the position was read against a calibration
+    pub floor: Option<Floor>,
+    pub direction: Direction,
+    pub doors: DoorState,
+}
+
+/// Estimated time for a car to answer the call after the stops it already has, None when it cannot tell
+///
+/// The car is followed through its calls in the order collective control serves them.
+pub fn time_to_arrive(status: CarStatus, calls: &CallSet, call: Call) -> Option<Duration> {
+    let mut floor = status.floor?;
+    let mut direction = status.direction;
+
+    let mut calls = calls.clone();
+    calls.insert(call);
+
+    let mut time = Duration::ZERO;
+
+    // Every stop answers at least one call
+    for _ in 0..calls.iter().count() {
+        let next = calls.next(Some(floor), direction)?;
+
+        time += FLOOR_TIME * floor.index().abs_diff(next.index()) as u32;
+
+        let arriving = if next > floor {
+            Up
+        } else if next < floor {
+            Down
+        } else {
+            direction
+        };
+
+        floor = next;
+        direction = calls.departure(floor, arriving);
+
+        if calls.serve(floor, direction).contains(&call) {
+            return Some(time);
+        }
+
+        time += STOP_TIME;
+    }
+
+    None
+}
+
+#[derive(Clone)]
+struct DispatchedCar {
+    calls: Arc<RwLock<CallSet>>,
+    emergency: Arc<AtomicBool>,
+    available: Arc<AtomicBool>,
+    status: Arc<RwLock<CarStatus>>,
+}
+
+/// Group control: hall calls from any panel go to the car expected to arrive first.
+///
+/// Car calls stay with the car whose button was pressed.
+#[derive(Clone, Default)]
+pub struct Dispatcher {
+    cars: Vec<DispatchedCar>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Dispatcher::default()
+    }
+
+    /// Adds the next car of the fleet, through what its floor thread shares
+    pub fn add_car(
+        &mut self,
+        calls: Arc<RwLock<CallSet>>,
+        emergency: Arc<AtomicBool>,
+        available: Arc<AtomicBool>,
+        status: Arc<RwLock<CarStatus>>,
+    ) {
+        self.cars.push(DispatchedCar {
+            calls,
+            emergency,
+            available,
+            status,
+        });
+    }
+
+    /// Whether a car already took the call
+    pub fn is_pending(&self, call: Call) -> bool {
+        self.cars
+            .iter()
+            .any(|car| car.calls.read().unwrap().contains(call))
+    }
+
+    /// Gives the call to the car arriving first, None when every car is in emergency, unavailable or excluded
+    ///
+    /// A car whose position is unknown is only chosen when no other one can take the call.
+    pub fn assign(&self, call: Call, excluded: Option<Elevator>) -> Option<Elevator> {
+        let (elevator, car, _) = self
+            .cars
+            .iter()
+            .enumerate()
+            .map(|(index, car)| (Elevator(index), car))
+            .filter(|(elevator, car)| {
+                Some(*elevator) != excluded
+                    && !car.emergency.load(Relaxed)
+                    && car.available.load(Relaxed)
+            })
+            .map(|(elevator, car)| {
+                let status = *car.status.read().unwrap();
+                let time = time_to_arrive(status, &car.calls.read().unwrap(), call);
+
+                (elevator, car, time.unwrap_or(Duration::MAX))
+            })
+            .min_by_key(|(_, _, time)| *time)?;
+
+        car.calls.write().unwrap().insert(call);
+
+        Some(elevator)
+    }
+}
@@ -0,0 +1,126 @@
+use crate::common::seconds;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Timing of the door cycle at each stop, in seconds in building.toml.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoorConfig {
+    /// Time the doors take to open or to close completely
+    #[serde(with = "seconds")]
+    pub travel: Duration,
+    /// Time the doors stay open before closing by themselves
+    #[serde(with = "seconds")]
+    pub dwell: Duration,
+}
+
+impl Default for DoorConfig {
+    fn default() -> Self {
+        DoorConfig {
+            travel: Duration::from_secs(1),
+            dwell: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoorState {
+    Closed,
+    Opening,
+    /// Dwelling until the doors close by themselves or are told to
+    Open,
+    Closing,
+    /// Opening again after closing was interrupted, from wherever the doors were
+    Reopening,
+}
+
+/// Door-open and door-close buttons inside the car.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoorCommand {
+    Open,
+    Close,
+}
+
+/// Logical doors of one car, there is no door actuator on the rig.
+///
+/// The motor may only be energised while the doors are [`DoorState::Closed`].
+#[derive(Clone, Debug)]
+pub struct Doors {
+    config: DoorConfig,
+    state: DoorState,
+    /// When the current state was entered
+    since: Instant,
+    /// Time left to open completely while reopening
+    reopening: Duration,
+}
+
+impl Doors {
+    pub fn new(config: DoorConfig) -> Self {
+        Doors {
+            config,
+            state: DoorState::Closed,
+            since: Instant::now(),
+            reopening: Duration::ZERO,
+        }
+    }
+
+    pub fn state(&self) -> DoorState {
+        self.state
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == DoorState::Closed
+    }
+
+    /// Opens the doors on a stop, or keeps them open a full dwell when they already are
+    pub fn open(&mut self, now: Instant) -> Option<DoorState> {
+        match self.state {
+            DoorState::Closed => self.enter(DoorState::Opening, now),
+            DoorState::Open => {
+                self.since = now;
+                None
+            }
+            DoorState::Closing => {
+                // Back out the distance already closed
+                self.reopening = now
+                    .saturating_duration_since(self.since)
+                    .min(self.config.travel);
+                self.enter(DoorState::Reopening, now)
+            }
+            DoorState::Opening | DoorState::Reopening => None,
+        }
+    }
+
+    /// Applies a button of the car, returning the new state when it changes
+    ///
+    /// Door-open only works with the car stopped at a landing, which is up to the caller.
+    pub fn command(&mut self, command: DoorCommand, now: Instant) -> Option<DoorState> {
+        match (command, self.state) {
+            (DoorCommand::Open, _) => self.open(now),
+            (DoorCommand::Close, DoorState::Open) => self.enter(DoorState::Closing, now),
+            (DoorCommand::Close, _) => None,
+        }
+    }
+
+    /// Advances the cycle with the time, returning the new state when it changes
+    pub fn update(&mut self, now: Instant) -> Option<DoorState> {
+        let elapsed = now.saturating_duration_since(self.since);
+
+        match self.state {
+            DoorState::Opening if elapsed >= self.config.travel => self.enter(DoorState::Open, now),
+            DoorState::Reopening if elapsed >= self.reopening => self.enter(DoorState::Open, now),
+            DoorState::Open if elapsed >= self.config.dwell => self.enter(DoorState::Closing, now),
+            DoorState::Closing if elapsed >= self.config.travel => {
+                self.enter(DoorState::Closed, now)
+            }
+            _ => None,
+        }
+    }
+
+    fn enter(&mut self, state: DoorState, now: Instant) -> Option<DoorState> {
+        self.state = state;
+        self.since = now;
+
+        Some(state)
+    }
+}
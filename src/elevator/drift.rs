@@ -0,0 +1,99 @@
+use crate::common::Floor;
+use crate::elevator::landings::Landings;
+
+// Past this many ticks of accumulated slip the belt or the encoder coupling needs a look. It is the width
+// of the ±100-tick margin landings had before sensor bands were stored: without the correction the car
+// would already stop outside the sensor it levels on.
+const WARNING_THRESHOLD: i32 = 200;
+
+/// Offset between the raw encoder and the shaft, learned from the floor sensor edges crossed in service.
+#[derive(Default)]
+pub struct DriftCorrection {
+    offset: i32,
+    last_position: Option<i32>,
+    last_sensors: Vec<bool>,
+    warned: bool,
+}
+
+impl DriftCorrection {
+    pub fn new() -> Self {
+        DriftCorrection::default()
+    }
+
+    /// Ticks added to the raw encoder count
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    pub fn correct(&self, raw: i32) -> i32 {
+        raw + self.offset
+    }
+
+    /// Feeds a raw encoder count with the sensor levels read at the same time
+    ///
+    /// When a sensor switched since the last sample, the calibrated edge must lie between both
+    /// positions; the offset moves by how far outside it was, and that correction is returned.
+    pub fn observe(&mut self, raw: i32, sensors: &[bool], landings: &Landings) -> Option<i32> {
+        let position = self.correct(raw);
+        let last_position = self.last_position.replace(position);
+        let last_sensors = std::mem::replace(&mut self.last_sensors, sensors.to_vec());
+
+        let last_position = last_position.filter(|last| *last != position)?;
+
+        if last_sensors.len() != sensors.len() {
+            return None;
+        }
+
+        let rising = position > last_position;
+        let (low, high) = (last_position.min(position), last_position.max(position));
+
+        let mut correction = None;
+
+        for (landing, (was, is)) in last_sensors.iter().zip(sensors).enumerate() {
+            if was == is {
+                continue;
+            }
+
+            let Some(band) = landings.band(Floor(landing)) else {
+                continue;
+            };
+
+            // Going up the car enters a band by its lower edge and leaves by the upper one
+            let edge = if *is == rising {
+                band.lower
+            } else {
+                band.upper
+            };
+
+            let error = if edge < low {
+                edge - low
+            } else if edge > high {
+                edge - high
+            } else {
+                0
+            };
+
+            correction = Some(correction.unwrap_or(0) + error);
+        }
+
+        if let Some(correction) = correction {
+            self.offset += correction;
+
+            if let Some(last) = self.last_position.as_mut() {
+                *last += correction;
+            }
+        }
+
+        correction
+    }
+
+    /// Total drift the first time it goes past the warning threshold, again only after coming back under it
+    pub fn warning(&mut self) -> Option<i32> {
+        let exceeded = self.offset.abs() > WARNING_THRESHOLD;
+        let first = exceeded && !self.warned;
+
+        self.warned = exceeded;
+
+        first.then_some(self.offset)
+    }
+}
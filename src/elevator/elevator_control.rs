@@ -2,283 +2,612 @@ use crate::common::{
     Direction::{self, Stop},
     Elevator, Floor,
 };
+use crate::elevator::building::{Building, CarWiring};
+use crate::elevator::calibration_control::CalibrationLimits;
+use crate::elevator::calibration_file::ElevatorCalibration;
+use crate::elevator::calls::{Call, CallSet};
+use crate::elevator::dispatcher::{CarStatus, Dispatcher};
+use crate::elevator::doors::{DoorCommand, DoorState, Doors};
+use crate::elevator::drift::DriftCorrection;
+use crate::elevator::emergency_stop::EmergencyConfig;
+use crate::elevator::failsafe_control::{self, FailsafeConfig};
+use crate::elevator::landings::Landings;
+use crate::elevator::panel_control::CarPanel;
+use crate::elevator::trajectory::TrajectoryLimits;
 use crate::elevator::{calibration_control, floor_control, panel_control, temperature_control};
-use crate::gpio::{engine_control::EngineControl, pid::PidController};
-use crate::i2c::ssd1306::SSD1306;
+use crate::error::{Error, Result};
+use crate::gpio::{
+    engine_control::EngineControl,
+    floor_sensors::{FloorSensors, GpioFloorSensors},
+    motor_driver::{MotorDriver, SharedMotor},
+    motor_safety,
+    pid::PidController,
+};
+use crate::i2c::{display::StatusDisplay, ssd1306::SSD1306};
 use crate::uart::esp32::{Encoder, Esp32};
-use rppal::gpio::{Gpio, InputPin};
+use crate::uart::transport::Transport;
+use rppal::gpio::Gpio;
+use rppal::uart::Uart;
 use std::{
-    collections::VecDeque,
-    sync::{atomic::AtomicBool, Arc, Mutex, RwLock},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc, Mutex, PoisonError, RwLock,
+    },
 };
 use stoppable_thread::StoppableHandle;
 
-#[derive(Default)]
-pub struct FloorsPosition {
-    pub ground: i32,
-    pub first: i32,
-    pub second: i32,
-    pub third: i32,
-}
-
-pub struct SensorPins {
-    pub ground_sensor_pin: InputPin,
-    pub first_sensor_pin: InputPin,
-    pub second_sensor_pin: InputPin,
-    pub third_sensor_pin: InputPin,
-}
-
 pub struct ElevatorState {
     pub elevator: Elevator,
     pub encoder: Encoder,
+    pub building: Arc<Building>,
 
-    pub engine_control: EngineControl,
+    pub engine_control: Box<dyn MotorDriver>,
     pub pid: PidController,
-    pub sensors: SensorPins,
+    /// Bounds of the reference the car follows on each trip
+    pub trajectory: TrajectoryLimits,
+    pub sensors: Box<dyn FloorSensors>,
+    pub landings: Landings,
+    pub drift: DriftCorrection,
 
-    pub queue: Arc<RwLock<VecDeque<Floor>>>,
+    pub calls: Arc<RwLock<CallSet>>,
     pub emergency: Arc<AtomicBool>,
-
-    pub current_floor: Floor,
+    /// Operator request to leave the emergency, taken by the floor thread
+    pub reset: Arc<AtomicBool>,
+    /// How a trip ends when the emergency button is pressed
+    pub emergency_stop: EmergencyConfig,
+    /// Pressed door button, taken by the floor thread
+    pub door_command: Arc<Mutex<Option<DoorCommand>>>,
+    pub doors: Doors,
+    /// Shared by the fleet, set by the failsafe while the ESP32 does not answer
+    pub out_of_service: Arc<AtomicBool>,
+    /// Whether the dispatcher may give the car hall calls, cleared while it recalibrates or is out of service
+    pub available: Arc<AtomicBool>,
+
+    /// None until the position was read against a calibration
+    pub current_floor: Option<Floor>,
+    /// Direction of travel, kept between stops while calls remain and Stop once the car is idle
     pub current_direction: Direction,
+    /// Copy of the floor, direction and doors read by the dispatcher
+    pub status: Arc<RwLock<CarStatus>>,
 }
 
-pub struct ElevatorControl {
-    esp32: Arc<Mutex<Esp32>>,
-    ssd1306: Arc<Mutex<SSD1306>>,
+impl ElevatorState {
+    pub fn wiring(&self) -> &CarWiring {
+        self.building.car(self.elevator)
+    }
+
+    /// Shares the current floor, direction and doors, to be called whenever any of them changes
+    pub fn publish(&self) {
+        *self.status.write().unwrap() = CarStatus {
+            floor: self.current_floor,
+            direction: self.current_direction,
+            doors: self.doors.state(),
+        };
+    }
+}
+
+/// One car of the fleet and the worker driving it.
+struct Car {
+    state: Arc<Mutex<ElevatorState>>,
+    // Reachable without the car lock, which a crashed thread may have poisoned
+    motor: SharedMotor,
+    emergency: Arc<AtomicBool>,
+    reset: Arc<AtomicBool>,
+    thread: Option<StoppableHandle<()>>,
+}
+
+pub struct ElevatorControl<T: Transport + 'static = Uart, D: StatusDisplay + 'static = SSD1306> {
+    esp32: Arc<Mutex<Esp32<T>>>,
+    display: Arc<Mutex<D>>,
+    building: Arc<Building>,
+
+    /// In the order of the building topology, indexed by [`Elevator`]
+    fleet: Vec<Car>,
+    /// Only under group control
+    dispatcher: Option<Dispatcher>,
 
-    elevator_1: Arc<Mutex<ElevatorState>>,
-    elevator_2: Arc<Mutex<ElevatorState>>,
+    calibration_file: PathBuf,
 
-    floors_range: Arc<RwLock<FloorsPosition>>,
+    out_of_service: Arc<AtomicBool>,
+    failsafe: FailsafeConfig,
 
+    calibrations: Vec<Option<ElevatorCalibration>>,
+    calibration_limits: CalibrationLimits,
+    abort_calibration: Arc<AtomicBool>,
+
+    failsafe_thread: Option<StoppableHandle<()>>,
     temperature_thread: Option<StoppableHandle<()>>,
     panel_thread: Option<StoppableHandle<()>>,
-    elevator_1_thread: Option<StoppableHandle<()>>,
-    elevator_2_thread: Option<StoppableHandle<()>>,
 
     ready: bool,
 }
 
-impl Drop for ElevatorControl {
+impl<T: Transport + 'static, D: StatusDisplay + 'static> Drop for ElevatorControl<T, D> {
     fn drop(&mut self) {
-        if self.temperature_thread.is_some() {
+        if self.failsafe_thread.is_some() {
             println!("WARNING: stop() was not called before dropping ElevatorControl. This may hinder the cleanup process.");
             println!("To avoid this warning, call stop() before dropping ElevatorControl.");
-            self.stop();
+            if let Err(e) = self.stop() {
+                eprintln!("Couldn't stop elevator cleanly: {}", e);
+            }
         }
     }
 }
 
 impl ElevatorControl {
-    pub fn new() -> Self {
+    pub fn new(building: Building) -> Result<Self> {
+        motor_safety::install_panic_hook();
+
         // Init
-        let gpio = Gpio::new().unwrap();
-        let esp32 = Esp32::new();
-        let mut ssd1306 = SSD1306::new();
-
-        ssd1306.update_floor(Elevator::One, Floor::Undefined);
-        ssd1306.update_floor(Elevator::Two, Floor::Undefined);
-        ssd1306.update_direction(Elevator::One, Stop);
-        ssd1306.update_direction(Elevator::Two, Stop);
-
-        let mut elevator_1 = ElevatorState {
-            elevator: Elevator::One,
-            encoder: Encoder::One,
-            engine_control: EngineControl::new(Elevator::One),
-            pid: PidController::new(),
-            sensors: SensorPins {
-                ground_sensor_pin: gpio.get(18).unwrap().into_input_pulldown(),
-                first_sensor_pin: gpio.get(23).unwrap().into_input_pulldown(),
-                second_sensor_pin: gpio.get(24).unwrap().into_input_pulldown(),
-                third_sensor_pin: gpio.get(25).unwrap().into_input_pulldown(),
-            },
-            current_floor: Floor::Undefined,
-            current_direction: Stop,
-            queue: Arc::new(RwLock::new(VecDeque::new())),
-            emergency: Arc::new(AtomicBool::new(false)),
-        };
+        let gpio = Gpio::new()?;
+        let esp32 = Esp32::new()?;
+        let display = SSD1306::new(building.cars.len())?;
+
+        let cars = building
+            .cars
+            .iter()
+            .map(|wiring| {
+                let motor = SharedMotor::new(EngineControl::with_pins(
+                    &gpio,
+                    wiring.motor.dir1,
+                    wiring.motor.dir2,
+                    wiring.motor.potm,
+                )?);
+
+                motor_safety::register(motor.clone());
+
+                let sensors: Box<dyn FloorSensors> =
+                    Box::new(GpioFloorSensors::new(&gpio, &wiring.sensor_pins)?);
+
+                Ok((motor, sensors))
+            })
+            .collect::<Result<_>>()?;
+
+        Self::with_parts(building, esp32, display, cars)
+    }
+}
 
-        elevator_1.engine_control.set_direction(Stop);
-
-        let mut elevator_2 = ElevatorState {
-            elevator: Elevator::Two,
-            encoder: Encoder::Two,
-            engine_control: EngineControl::new(Elevator::Two),
-            pid: PidController::new(),
-            sensors: SensorPins {
-                ground_sensor_pin: gpio.get(17).unwrap().into_input_pulldown(),
-                first_sensor_pin: gpio.get(27).unwrap().into_input_pulldown(),
-                second_sensor_pin: gpio.get(22).unwrap().into_input_pulldown(),
-                third_sensor_pin: gpio.get(6).unwrap().into_input_pulldown(),
-            },
-            current_floor: Floor::Undefined,
-            current_direction: Stop,
-            queue: Arc::new(RwLock::new(VecDeque::new())),
-            emergency: Arc::new(AtomicBool::new(false)),
-        };
+impl<T: Transport + 'static, D: StatusDisplay + 'static> ElevatorControl<T, D> {
+    /// Fleet driven through the given board and display, with a motor and floor sensors per car in topology order
+    ///
+    /// The motors are not registered for the safety shutdown, which is left to the caller.
+    pub fn with_parts(
+        building: Building,
+        esp32: Esp32<T>,
+        mut display: D,
+        cars: Vec<(SharedMotor, Box<dyn FloorSensors>)>,
+    ) -> Result<Self> {
+        if cars.len() != building.cars.len() {
+            return Err(Error::Building(format!(
+                "{} motors for {} cars",
+                cars.len(),
+                building.cars.len()
+            )));
+        }
+
+        let building = Arc::new(building);
+
+        let out_of_service = Arc::new(AtomicBool::new(false));
+
+        let mut fleet = Vec::with_capacity(building.cars.len());
+        let mut dispatcher = building.group_control.then(Dispatcher::new);
+
+        for (elevator, (motor, sensors)) in building.elevators().zip(cars) {
+            let wiring = building.car(elevator);
+
+            display.update_floor(elevator, building.name(None))?;
+            display.update_direction(elevator, Stop)?;
+
+            let mut state = ElevatorState {
+                elevator,
+                encoder: wiring.encoder,
+                building: building.clone(),
+                engine_control: Box::new(motor.clone()),
+                pid: PidController::new(wiring.pid),
+                trajectory: wiring.trajectory,
+                sensors,
+                landings: Landings::default(),
+                drift: DriftCorrection::new(),
+                current_floor: None,
+                current_direction: Stop,
+                status: Arc::new(RwLock::new(CarStatus {
+                    floor: None,
+                    direction: Stop,
+                    doors: DoorState::Closed,
+                })),
+                calls: Arc::new(RwLock::new(CallSet::new())),
+                emergency: Arc::new(AtomicBool::new(false)),
+                reset: Arc::new(AtomicBool::new(false)),
+                emergency_stop: wiring.emergency_stop,
+                door_command: Arc::new(Mutex::new(None)),
+                doors: Doors::new(wiring.doors),
+                out_of_service: out_of_service.clone(),
+                available: Arc::new(AtomicBool::new(false)),
+            };
 
-        elevator_2.engine_control.set_direction(Stop);
+            state.engine_control.set_direction(Stop)?;
 
-        let floors_range = FloorsPosition::default();
+            if let Some(dispatcher) = &mut dispatcher {
+                dispatcher.add_car(
+                    state.calls.clone(),
+                    state.emergency.clone(),
+                    state.available.clone(),
+                    state.status.clone(),
+                );
+            }
+
+            fleet.push(Car {
+                emergency: state.emergency.clone(),
+                reset: state.reset.clone(),
+                state: Arc::new(Mutex::new(state)),
+                motor,
+                thread: None,
+            });
+        }
 
         let esp32 = Arc::new(Mutex::new(esp32));
-        let ssd1306 = Arc::new(Mutex::new(ssd1306));
+        let display = Arc::new(Mutex::new(display));
 
         // Return
-        Self {
+        Ok(Self {
             esp32,
-            ssd1306,
-            elevator_1: Arc::new(Mutex::new(elevator_1)),
-            elevator_2: Arc::new(Mutex::new(elevator_2)),
+            display,
+            calibrations: vec![None; building.cars.len()],
+            building,
+            fleet,
+            dispatcher,
+            out_of_service,
+            failsafe: FailsafeConfig::default(),
+            calibration_limits: CalibrationLimits::default(),
+            abort_calibration: Arc::new(AtomicBool::new(false)),
+            failsafe_thread: None,
             temperature_thread: None,
             panel_thread: None,
-            elevator_1_thread: None,
-            elevator_2_thread: None,
-            floors_range: Arc::new(RwLock::new(floors_range)),
+            calibration_file: PathBuf::from(calibration_control::CALIBRATION_FILE),
             ready: false,
-        }
+        })
+    }
+
+    /// Replaces the default communication-loss failsafe tuning, must be called before init()
+    pub fn set_failsafe(&mut self, config: FailsafeConfig) {
+        self.failsafe = config;
+    }
+
+    /// Reads and saves the calibration at another path than the working directory, must be called before init()
+    pub fn set_calibration_file(&mut self, path: impl Into<PathBuf>) {
+        self.calibration_file = path.into();
+    }
+
+    /// Replaces the default bounds of the calibration phases
+    pub fn set_calibration_limits(&mut self, limits: CalibrationLimits) {
+        self.calibration_limits = limits;
     }
 
-    pub fn init(&mut self) {
+    /// Flag aborting any running calibration once set, e.g. by a signal handler
+    pub fn calibration_abort_flag(&self) -> Arc<AtomicBool> {
+        self.abort_calibration.clone()
+    }
+
+    pub fn init(&mut self) -> Result<()> {
         // Calibration
-        match calibration_control::read_calibration() {
-            Ok(floors_range) => {
-                *self.floors_range.write().unwrap() = floors_range;
-            }
-            Err(_) => {
-                println!("Calibration file not found.");
+        let landings = self.building.landings.len();
+        let cars = self.fleet.len();
+
+        self.calibrations =
+            calibration_control::read_calibration(&self.calibration_file, landings, cars)
+                .unwrap_or_else(|e| {
+                    println!("Couldn't read calibration: {}", e);
+                    vec![None; cars]
+                });
+
+        let mut calibrated = false;
+
+        for (car, calibration) in self.fleet.iter().zip(self.calibrations.iter_mut()) {
+            let calibration = match calibration {
+                Some(calibration) => calibration,
+                None => {
+                    calibrated = true;
+                    calibration.insert(calibration_control::start(
+                        self.esp32.clone(),
+                        self.display.clone(),
+                        car.state.clone(),
+                        self.calibration_limits,
+                        &self.abort_calibration,
+                    )?)
+                }
+            };
 
-                calibration_control::start(
-                    self.esp32.clone(),
-                    self.elevator_1.clone(),
-                    self.floors_range.clone(),
-                );
+            car.state.lock().unwrap().landings = Landings::from_calibration(calibration);
+        }
 
-                calibration_control::write_calibration(&self.floors_range.read().unwrap());
-            }
+        if calibrated {
+            self.save_calibrations()?;
         }
 
+        // Failsafe thread
+        self.failsafe_thread = Some(failsafe_control::start(
+            self.esp32.clone(),
+            self.display.clone(),
+            self.out_of_service.clone(),
+            self.failsafe,
+            self.building.cars.iter().map(|car| car.encoder).collect(),
+        ));
+
         // Temperature thread
         self.temperature_thread = Some(temperature_control::start(
             self.esp32.clone(),
-            self.ssd1306.clone(),
+            self.display.clone(),
+            self.building
+                .cars
+                .iter()
+                .map(|car| car.thermometer)
+                .collect(),
         ));
 
-        // Get current floor for elevator 1
-        {
-            let current_position = self.esp32.lock().unwrap().get_encoder_value(Encoder::One);
-            let floors_range = self.floors_range.read().unwrap();
-            let mut elevator = self.elevator_1.lock().unwrap();
-
-            elevator.current_floor = if current_position < floors_range.first - 100 {
-                Floor::Ground
-            } else if current_position < floors_range.second - 100 {
-                Floor::First
-            } else if current_position < floors_range.third - 100 {
-                Floor::Second
-            } else {
-                Floor::Third
-            };
-
-            self.ssd1306
-                .lock()
-                .unwrap()
-                .update_floor(Elevator::One, elevator.current_floor);
-        }
-
-        // Get current floor for elevator 2
-        {
-            let current_position = self.esp32.lock().unwrap().get_encoder_value(Encoder::Two);
-            let floors_range = self.floors_range.read().unwrap();
-            let mut elevator = self.elevator_2.lock().unwrap();
-
-            elevator.current_floor = if current_position < floors_range.first - 100 {
-                Floor::Ground
-            } else if current_position < floors_range.second - 100 {
-                Floor::First
-            } else if current_position < floors_range.third - 100 {
-                Floor::Second
-            } else {
-                Floor::Third
-            };
-
-            self.ssd1306
-                .lock()
-                .unwrap()
-                .update_floor(Elevator::Two, elevator.current_floor);
+        // Get current floor of each elevator
+        for car in &self.fleet {
+            self.update_current_floor(&car.state)?;
         }
 
         // Panel thread
-        {
-            let elevator_1 = self.elevator_1.lock().unwrap();
-            let elevator_2 = self.elevator_2.lock().unwrap();
+        let cars = self
+            .fleet
+            .iter()
+            .map(|car| {
+                let state = car.state.lock().unwrap();
+
+                CarPanel {
+                    calls: state.calls.clone(),
+                    emergency: state.emergency.clone(),
+                    door_command: state.door_command.clone(),
+                }
+            })
+            .collect();
+
+        self.panel_thread = Some(panel_control::start(
+            self.esp32.clone(),
+            self.building.clone(),
+            cars,
+            self.dispatcher.clone(),
+        ));
 
-            self.panel_thread = Some(panel_control::start(
+        // Floors threads
+        for car in &mut self.fleet {
+            car.thread = Some(floor_control::start(
                 self.esp32.clone(),
-                (elevator_1.queue.clone(), elevator_2.queue.clone()),
-                (elevator_1.emergency.clone(), elevator_2.emergency.clone()),
+                self.display.clone(),
+                car.state.clone(),
             ));
         }
 
-        // Floors thread
-        self.elevator_1_thread = Some(floor_control::start(
-            self.esp32.clone(),
-            self.ssd1306.clone(),
-            self.elevator_1.clone(),
-            self.floors_range.clone(),
-        ));
+        self.ready = true;
 
-        self.elevator_2_thread = Some(floor_control::start(
-            self.esp32.clone(),
-            self.ssd1306.clone(),
-            self.elevator_2.clone(),
-            self.floors_range.clone(),
-        ));
+        Ok(())
+    }
 
-        self.ready = true;
+    fn save_calibrations(&self) -> Result<()> {
+        let calibrations: Vec<_> = self.calibrations.iter().flatten().cloned().collect();
+
+        calibration_control::write_calibration(&self.calibration_file, &calibrations)
     }
 
-    pub fn stop(&mut self) {
-        if let Some(handle) = self.temperature_thread.take() {
-            handle.stop().join().unwrap();
+    fn update_current_floor(&self, elevator: &Arc<Mutex<ElevatorState>>) -> Result<()> {
+        let mut elevator = elevator.lock().unwrap();
+
+        let raw_position = self
+            .esp32
+            .lock()
+            .unwrap()
+            .get_encoder_value(elevator.encoder)?;
+        let current_position = elevator.drift.correct(raw_position);
+
+        elevator.current_floor = elevator.landings.nearest(current_position);
+        elevator.publish();
+
+        self.display.lock().unwrap().update_floor(
+            elevator.elevator,
+            self.building.name(elevator.current_floor),
+        )
+    }
+
+    // Drops the pending calls of a car and their lamps, under group control its hall calls go to the other cars
+    fn drain_calls(&self, state: &ElevatorState) -> Result<()> {
+        let wiring = state.wiring();
+        let (first, last) = wiring.block();
+
+        let pending = state.calls.write().unwrap().take();
+
+        let cleared =
+            self.esp32
+                .lock()
+                .unwrap()
+                .write_button_in_range(first, last, &wiring.lamps(&[]));
+
+        if let Some(dispatcher) = &self.dispatcher {
+            for call in pending
+                .into_iter()
+                .filter(|call| matches!(call, Call::Hall(..)))
+            {
+                let dispatched = panel_control::dispatch(
+                    &self.esp32,
+                    &self.building,
+                    dispatcher,
+                    call,
+                    Some(state.elevator),
+                );
+
+                if let Err(e) = dispatched {
+                    eprintln!(
+                        "Couldn't hand over a call of elevator {}: {}",
+                        state.elevator, e
+                    );
+                }
+            }
         }
 
-        if let Some(handle) = self.panel_thread.take() {
-            handle.stop().join().unwrap();
+        cleared
+    }
+
+    /// Cars stopped by their emergency button
+    pub fn in_emergency(&self) -> Vec<Elevator> {
+        self.building
+            .elevators()
+            .zip(&self.fleet)
+            .filter(|(_, car)| car.emergency.load(Relaxed))
+            .map(|(elevator, _)| elevator)
+            .collect()
+    }
+
+    /// Returns a car in emergency to service, the operator having checked it is safe
+    ///
+    /// The floor thread relevels the car at the nearest landing, clears the emergency lamp and opens the doors.
+    pub fn reset_emergency(&self, elevator: Elevator) -> Result<()> {
+        let car = self
+            .fleet
+            .get(elevator.index())
+            .ok_or_else(|| Error::Building(format!("no elevator {}", elevator)))?;
+
+        if !car.emergency.load(Relaxed) {
+            println!("Elevator {} is not in emergency.", elevator);
+            return Ok(());
         }
 
-        if let Some(handle) = self.elevator_1_thread.take() {
-            handle.stop().join().unwrap();
+        car.reset.store(true, Relaxed);
+
+        Ok(())
+    }
+
+    /// Calibrates one car again while the rest of the fleet keeps serving
+    ///
+    /// The car finishes its current trip, drops its pending calls, sweeps the shaft and returns to service.
+    /// The calls it received during the sweep are dropped as well.
+    /// The calibration file is only replaced when the sweep succeeds.
+    pub fn recalibrate(&mut self, elevator: Elevator) -> Result<()> {
+        let car = self
+            .fleet
+            .get_mut(elevator.index())
+            .ok_or_else(|| Error::Building(format!("no elevator {}", elevator)))?;
+        let state = car.state.clone();
+
+        // The sweep would drive a car the failsafe stopped
+        if self.out_of_service.load(Relaxed) {
+            return Err(Error::Interlock(format!(
+                "elevator {} is out of service",
+                elevator
+            )));
         }
 
-        if let Some(handle) = self.elevator_2_thread.take() {
-            handle.stop().join().unwrap();
+        // Pause the car, its floor thread holds the car lock while running
+        let paused = car.thread.take().is_some_and(|handle| {
+            if handle.stop().join().is_err() {
+                eprintln!("The elevator {} thread had crashed", elevator);
+            }
+
+            true
+        });
+
+        // Drop the pending calls and their lamps, unless the operator stopped the car
+        let drained = {
+            let mut state = state.lock().unwrap();
+
+            // No hall call is given to the car until its floor thread runs again
+            state.available.store(false, Relaxed);
+
+            // The sweep drives the motor, which the doors interlock
+            floor_control::close_doors(&self.display, &mut state);
+
+            if state.emergency.load(Relaxed) {
+                Err(Error::Interlock(format!(
+                    "elevator {} is in emergency, reset it before calibrating",
+                    elevator
+                )))
+            } else {
+                self.drain_calls(&state)
+            }
+        };
+
+        let swept = drained.is_ok();
+
+        let result = drained
+            .and_then(|_| {
+                calibration_control::start(
+                    self.esp32.clone(),
+                    self.display.clone(),
+                    state.clone(),
+                    self.calibration_limits,
+                    &self.abort_calibration,
+                )
+            })
+            .and_then(|calibration| {
+                self.calibrations[elevator.index()] = Some(calibration);
+                self.save_calibrations()
+            });
+
+        // The sweep may have moved the car whatever its outcome
+        let result = result.and(self.update_current_floor(&state));
+
+        // Calls pressed during the sweep were never answered, they are dropped like the ones before it
+        let result = {
+            let state = state.lock().unwrap();
+
+            if swept && !state.emergency.load(Relaxed) {
+                result.and(self.drain_calls(&state))
+            } else {
+                result
+            }
+        };
+
+        if paused {
+            self.fleet[elevator.index()].thread = Some(floor_control::start(
+                self.esp32.clone(),
+                self.display.clone(),
+                state,
+            ));
         }
 
-        {
-            let mut elevator_1 = self.elevator_1.lock().unwrap();
-            elevator_1.engine_control.set_direction(Stop);
-            elevator_1.engine_control.set_potency(0.0);
+        result
+    }
+
+    /// Stops every thread and the motors, keeps going on failures and returns the first one
+    ///
+    /// Safe to call after a worker crashed: poisoned locks are recovered instead of unwrapped
+    pub fn stop(&mut self) -> Result<()> {
+        let mut handles = vec![
+            ("failsafe".to_string(), self.failsafe_thread.take()),
+            ("temperature".to_string(), self.temperature_thread.take()),
+            ("panel".to_string(), self.panel_thread.take()),
+        ];
+
+        for (elevator, car) in self.building.elevators().zip(&mut self.fleet) {
+            handles.push((format!("elevator {}", elevator), car.thread.take()));
+        }
 
-            let mut elevator_2 = self.elevator_2.lock().unwrap();
-            elevator_2.engine_control.set_direction(Stop);
-            elevator_2.engine_control.set_potency(0.0);
+        for (name, handle) in handles {
+            if let Some(handle) = handle {
+                if handle.stop().join().is_err() {
+                    eprintln!("The {} thread had crashed", name);
+                }
+            }
         }
 
-        let mut ssd1306 = self.ssd1306.lock().unwrap();
-        let mut esp32 = self.esp32.lock().unwrap();
+        let mut results: Vec<_> = self.fleet.iter_mut().map(|car| car.motor.stop()).collect();
 
-        ssd1306.update_direction(Elevator::One, Stop);
-        ssd1306.update_floor(Elevator::One, Floor::Ground);
-        ssd1306.update_temperature(Elevator::One, 0.0);
-        esp32.write_all_buttons(Elevator::One, &[false; 11]);
+        let mut display = self.display.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut esp32 = self.esp32.lock().unwrap_or_else(PoisonError::into_inner);
 
-        ssd1306.update_direction(Elevator::Two, Stop);
-        ssd1306.update_floor(Elevator::Two, Floor::Ground);
-        ssd1306.update_temperature(Elevator::Two, 0.0);
-        esp32.write_all_buttons(Elevator::Two, &[false; 11]);
+        for elevator in self.building.elevators() {
+            let wiring = self.building.car(elevator);
+            let (first, last) = wiring.block();
+
+            results.push(display.update_direction(elevator, Stop));
+            results.push(display.update_floor(elevator, self.building.name(Some(Floor(0)))));
+            results.push(display.update_temperature(elevator, 0.0));
+            results.push(esp32.write_button_in_range(first, last, &wiring.lamps(&[])));
+        }
 
         self.ready = false;
+
+        results.into_iter().collect()
     }
 }
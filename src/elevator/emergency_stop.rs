@@ -0,0 +1,100 @@
+use crate::common::{
+    Direction::{self, Down, Stop, Up},
+    Floor,
+};
+use crate::elevator::landings::Landings;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How a trip ends when the emergency button of the car is pressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopProfile {
+    /// Brakes and cuts the power at once, wherever the car is
+    Hard,
+    /// Slows down at a limited rate and levels at the next landing the car can still stop at
+    Soft,
+}
+
+/// Emergency stop of a car, hard by default as the lab rig always did.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmergencyConfig {
+    pub profile: StopProfile,
+    /// Duty cycle shed per second by a soft stop
+    pub deceleration: f64,
+}
+
+impl Default for EmergencyConfig {
+    fn default() -> Self {
+        EmergencyConfig {
+            profile: StopProfile::Hard,
+            deceleration: 0.5,
+        }
+    }
+}
+
+/// Landing where a soft stop levels, the first one ahead whose whole sensor band lies beyond `horizon`
+pub fn landing_ahead(landings: &Landings, direction: Direction, horizon: i32) -> Option<Floor> {
+    let floors = (0..)
+        .map(Floor)
+        .map_while(|floor| Some((floor, landings.band(floor)?)));
+
+    match direction {
+        Up => floors
+            .filter(|(_, band)| band.lower > horizon)
+            .map(|(floor, _)| floor)
+            .next(),
+        Down => floors
+            .filter(|(_, band)| band.upper < horizon)
+            .map(|(floor, _)| floor)
+            .last(),
+        Stop => None,
+    }
+}
+
+/// Emergency stop under way, limits the duty cycle of a soft stop and measures the stop.
+#[derive(Clone, Debug)]
+pub struct EmergencyStop {
+    config: EmergencyConfig,
+    started: Instant,
+    position: i32,
+    /// Most duty cycle allowed at the last update
+    potency: f64,
+    updated: Instant,
+}
+
+impl EmergencyStop {
+    /// Starts from the duty cycle driving the car and its position when the button was seen
+    pub fn new(config: EmergencyConfig, potency: f64, position: i32, now: Instant) -> Self {
+        EmergencyStop {
+            config,
+            started: now,
+            position,
+            potency,
+            updated: now,
+        }
+    }
+
+    pub fn profile(&self) -> StopProfile {
+        self.config.profile
+    }
+
+    /// Most duty cycle allowed now, shedding the deceleration since the last update
+    pub fn limit(&mut self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.potency = (self.potency - self.config.deceleration * elapsed).max(0.0);
+        self.updated = now;
+
+        self.potency
+    }
+
+    /// Encoder ticks travelled and time taken since the button was seen
+    pub fn measure(&self, position: i32, now: Instant) -> (i32, Duration) {
+        (
+            (position - self.position).abs(),
+            now.saturating_duration_since(self.started),
+        )
+    }
+}
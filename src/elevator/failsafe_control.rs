@@ -0,0 +1,126 @@
+use crate::common::Elevator;
+use crate::gpio::motor_safety::{self, WorkerGuard};
+use crate::i2c::display::StatusDisplay;
+use crate::uart::esp32::{Encoder, Esp32, LinkHealth};
+use crate::uart::transport::Transport;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use stoppable_thread::StoppableHandle;
+
+const PERIOD: Duration = Duration::from_millis(100);
+
+/// Tuning of the communication-loss failsafe.
+#[derive(Clone, Copy, Debug)]
+pub struct FailsafeConfig {
+    /// Consecutive failed transactions before the elevator is taken out of service
+    pub max_failures: u32,
+    /// Time the link must answer without failures before service resumes
+    pub grace_period: Duration,
+}
+
+impl Default for FailsafeConfig {
+    fn default() -> Self {
+        FailsafeConfig {
+            max_failures: 3,
+            grace_period: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Decides from the link health when the elevator leaves and returns to service.
+pub struct Failsafe {
+    config: FailsafeConfig,
+    out_of_service: bool,
+}
+
+impl Failsafe {
+    pub fn new(config: FailsafeConfig) -> Self {
+        Failsafe {
+            config,
+            out_of_service: false,
+        }
+    }
+
+    pub fn is_out_of_service(&self) -> bool {
+        self.out_of_service
+    }
+
+    /// Feeds the latest link health, returning the new out-of-service state when it changes
+    pub fn update(&mut self, health: LinkHealth, now: Instant) -> Option<bool> {
+        if !self.out_of_service {
+            if health.consecutive_failures < self.config.max_failures {
+                return None;
+            }
+        } else {
+            let healthy_for = health
+                .last_failure
+                .map_or(Duration::MAX, |last| now.saturating_duration_since(last));
+
+            if health.consecutive_failures > 0 || healthy_for < self.config.grace_period {
+                return None;
+            }
+        }
+
+        self.out_of_service = !self.out_of_service;
+
+        Some(self.out_of_service)
+    }
+}
+
+/// Watches the link for the whole fleet, given by the encoder of each car in fleet order
+pub fn start<T: Transport + 'static, D: StatusDisplay + 'static>(
+    esp32: Arc<Mutex<Esp32<T>>>,
+    display: Arc<Mutex<D>>,
+    out_of_service: Arc<AtomicBool>,
+    config: FailsafeConfig,
+    encoders: Vec<Encoder>,
+) -> StoppableHandle<()> {
+    stoppable_thread::spawn(move |stopped| {
+        let _guard = WorkerGuard::new("Failsafe");
+
+        let mut failsafe = Failsafe::new(config);
+
+        while !stopped.get() {
+            let health = {
+                let mut esp32 = esp32.lock().unwrap();
+
+                // The other threads may be idle, so probe the board to notice when it answers again
+                if failsafe.is_out_of_service() {
+                    let _ = esp32.get_encoder_value(encoders[0]);
+                }
+
+                esp32.link_health()
+            };
+
+            if let Some(state) = failsafe.update(health, Instant::now()) {
+                if state {
+                    eprintln!(
+                        "ESP32 failed {} transactions in a row, elevator out of service",
+                        health.consecutive_failures
+                    );
+                } else {
+                    println!("ESP32 link restored, elevator back in service");
+                }
+
+                // Floor threads also stop their motor on the next tick once the flag is set
+                out_of_service.store(state, Relaxed);
+
+                if state {
+                    motor_safety::stop_all();
+                }
+
+                let mut display = display.lock().unwrap();
+
+                for elevator in (0..encoders.len()).map(Elevator) {
+                    if let Err(e) = display.update_service(elevator, !state) {
+                        eprintln!("Couldn't show service state of {}: {}", elevator, e);
+                    }
+                }
+            }
+
+            thread::sleep(PERIOD);
+        }
+    })
+}
@@ -1,46 +1,119 @@
-use super::elevator_control::{ElevatorState, FloorsPosition};
+use super::calls::Call;
+use super::doors::{DoorCommand, DoorState};
+use super::elevator_control::ElevatorState;
+use super::emergency_stop::{self, EmergencyStop, StopProfile};
+use super::landings::CarPosition;
+use super::trajectory::{Reference, Trajectory};
 use crate::common::{
     Direction::{Down, Stop, Up},
     Floor,
 };
-use crate::i2c::ssd1306::SSD1306;
-use crate::uart::esp32::{Button, Esp32};
+use crate::error::{CalibrationError, Error, Result};
+use crate::gpio::motor_safety::WorkerGuard;
+use crate::i2c::display::StatusDisplay;
+use crate::uart::esp32::Esp32;
+use crate::uart::transport::Transport;
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
-        Arc, Mutex, MutexGuard, RwLock,
+        Arc, Mutex, MutexGuard,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use stoppable_thread::StoppableHandle;
 
-pub fn start(
-    esp32: Arc<Mutex<Esp32>>,
-    ssd1306: Arc<Mutex<SSD1306>>,
+// Least duty cycle able to move the car
+const MIN_POTENCY: f64 = 0.05;
+
+// Most duty cycle inside the leveling zone of the target
+const LEVELING_POTENCY: f64 = 0.1;
+
+// Control periods the car needs to stop from its current speed, the travel of each one being the last sample
+const STOPPING_PERIODS: i32 = 5;
+
+pub fn start<T: Transport + 'static, D: StatusDisplay + 'static>(
+    esp32: Arc<Mutex<Esp32<T>>>,
+    display: Arc<Mutex<D>>,
     elevator: Arc<Mutex<ElevatorState>>,
-    floors_range: Arc<RwLock<FloorsPosition>>,
 ) -> StoppableHandle<()> {
     stoppable_thread::spawn(move |stopped| {
+        let _guard = WorkerGuard::new("Floor control");
+
         let mut elevator = elevator.lock().unwrap();
 
         while !stopped.get() {
-            if !elevator.emergency.load(Relaxed) {
-                let floor = elevator.queue.write().unwrap().pop_front();
-
-                if let Some(floor) = floor {
-                    let emergency = elevator.emergency.clone();
-
-                    move_to(
-                        &esp32,
-                        &ssd1306,
-                        &mut elevator,
-                        &floors_range,
-                        floor,
-                        emergency,
+            // The dispatcher leaves the car alone while the failsafe holds it
+            let in_service = !elevator.out_of_service.load(Relaxed);
+            elevator.available.store(in_service, Relaxed);
+
+            operate_doors(&display, &mut elevator);
+
+            if elevator.emergency.load(Relaxed) && elevator.reset.swap(false, Relaxed) {
+                if let Err(e) = recover(&esp32, &display, &mut elevator) {
+                    eprintln!(
+                        "Elevator {} couldn't leave the emergency: {}",
+                        elevator.elevator, e
                     );
 
-                    thread::sleep(Duration::from_secs(2));
+                    // Still stopped, the operator may reset it again
+                    let _ = elevator.engine_control.stop();
+                    elevator.emergency.store(true, Relaxed);
+                }
+            }
+
+            if !elevator.emergency.load(Relaxed) && !elevator.out_of_service.load(Relaxed) {
+                let floor = elevator
+                    .calls
+                    .read()
+                    .unwrap()
+                    .next(elevator.current_floor, elevator.current_direction);
+
+                match floor {
+                    // The car only leaves with the doors closed
+                    Some(floor) if elevator.doors.is_closed() => {
+                        let emergency = elevator.emergency.clone();
+
+                        let result = move_to(&esp32, &display, &mut elevator, floor, emergency);
+
+                        match result {
+                            Ok(true) => {
+                                let opened = elevator.doors.open(Instant::now());
+                                show_doors(&display, &elevator, opened);
+                            }
+                            Ok(false) => {}
+                            Err(e) => {
+                                eprintln!("Elevator {} trip aborted: {}", elevator.elevator, e);
+
+                                // The motor may still be driven with the last duty cycle
+                                let _ = elevator.engine_control.stop();
+
+                                // Link errors are retried until the failsafe takes the elevator out of service
+                                if !e.is_link() {
+                                    drop_calls(&esp32, &mut elevator, floor);
+                                }
+                            }
+                        }
+                    }
+                    // A call to the landing the car stands at with its doors open keeps them open
+                    Some(floor) if Some(floor) == elevator.current_floor => {
+                        if let Err(e) = answer(&esp32, &mut elevator, floor) {
+                            eprintln!(
+                                "Couldn't turn off the lamps of elevator {}: {}",
+                                elevator.elevator, e
+                            );
+                        }
+
+                        let opened = elevator.doors.open(Instant::now());
+                        show_doors(&display, &elevator, opened);
+                    }
+                    Some(_) => {}
+                    // Idle, the next call is answered from wherever it comes
+                    None if elevator.current_direction != Stop => {
+                        elevator.current_direction = Stop;
+                        elevator.publish();
+                    }
+                    None => {}
                 }
             }
 
@@ -49,95 +122,442 @@ pub fn start(
     })
 }
 
+// Applies the door buttons and advances the door cycle
+fn operate_doors(
+    display: &Arc<Mutex<impl StatusDisplay>>,
+    elevator: &mut MutexGuard<ElevatorState>,
+) {
+    let now = Instant::now();
+    let command = elevator.door_command.lock().unwrap().take();
+
+    // Only a car standing at a landing may open, not one stopped between floors
+    let standing = !elevator.emergency.load(Relaxed) && !elevator.out_of_service.load(Relaxed);
+
+    let commanded = command
+        .filter(|command| standing || *command == DoorCommand::Close)
+        .and_then(|command| elevator.doors.command(command, now));
+    let changed = elevator.doors.update(now).or(commanded);
+
+    show_doors(display, elevator, changed);
+}
+
+/// Closes the doors before the car is moved outside of a trip, e.g. to recalibrate it
+pub fn close_doors(
+    display: &Arc<Mutex<impl StatusDisplay>>,
+    elevator: &mut MutexGuard<ElevatorState>,
+) {
+    while !elevator.doors.is_closed() {
+        let now = Instant::now();
+        let changed = elevator
+            .doors
+            .command(DoorCommand::Close, now)
+            .or_else(|| elevator.doors.update(now));
+
+        show_doors(display, elevator, changed);
+
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// The doors are only logical, the display and the log are all there is to see of them
+fn show_doors(
+    display: &Arc<Mutex<impl StatusDisplay>>,
+    elevator: &MutexGuard<ElevatorState>,
+    changed: Option<DoorState>,
+) {
+    let Some(doors) = changed else {
+        return;
+    };
+
+    println!("Elevator {} doors {:?}.", elevator.elevator, doors);
+    elevator.publish();
+
+    if let Err(e) = display
+        .lock()
+        .unwrap()
+        .update_doors(elevator.elevator, doors)
+    {
+        eprintln!(
+            "Couldn't show the doors of elevator {}: {}",
+            elevator.elevator, e
+        );
+    }
+}
+
+// Relevels the car at the nearest landing and lets the passengers out, the car is in service again
+fn recover(
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    display: &Arc<Mutex<impl StatusDisplay>>,
+    elevator: &mut MutexGuard<ElevatorState>,
+) -> Result<()> {
+    println!("Elevator {} leaving the emergency.", elevator.elevator);
+
+    close_doors(display, elevator);
+
+    let raw_position = esp32.lock().unwrap().get_encoder_value(elevator.encoder)?;
+    let floor = elevator
+        .landings
+        .nearest(elevator.drift.correct(raw_position))
+        .ok_or(CalibrationError::NotFound)?;
+
+    // Only the emergency lamp, hall calls of other cars may be lit on the panel
+    let emergency_button = elevator.wiring().emergency;
+    esp32
+        .lock()
+        .unwrap()
+        .write_button(emergency_button, false)?;
+
+    elevator.current_direction = Stop;
+    elevator.emergency.store(false, Relaxed);
+    elevator.publish();
+
+    let emergency = elevator.emergency.clone();
+
+    if move_to(esp32, display, elevator, floor, emergency)? {
+        println!(
+            "Elevator {} back in service at {}.",
+            elevator.elevator,
+            elevator.building.name(Some(floor))
+        );
+
+        let opened = elevator.doors.open(Instant::now());
+        show_doors(display, elevator, opened);
+    }
+
+    Ok(())
+}
+
+// Encoder position the car levels at, a floor missing from the calibration cannot be served
+fn center(elevator: &ElevatorState, floor: Floor) -> Result<i32> {
+    elevator
+        .landings
+        .center(floor)
+        .ok_or_else(|| CalibrationError::Uncalibrated(floor).into())
+}
+
+// Ok(true) once the car stands at the landing, Ok(false) when the trip was interrupted between floors
 fn move_to(
-    esp32: &Arc<Mutex<Esp32>>,
-    ssd1306: &Arc<Mutex<SSD1306>>,
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    display: &Arc<Mutex<impl StatusDisplay>>,
     elevator: &mut MutexGuard<ElevatorState>,
-    floors_range: &Arc<RwLock<FloorsPosition>>,
-    floor: Floor,
+    mut floor: Floor,
     emergency: Arc<AtomicBool>,
-) {
-    if floor != elevator.current_floor {
+) -> Result<bool> {
+    // A car at its own landing only moves when off level, keeping its direction
+    if Some(floor) != elevator.current_floor {
+        let mut display = display.lock().unwrap();
+
+        if elevator
+            .current_floor
+            .is_some_and(|current| floor < current)
         {
-            let mut ssd1306 = ssd1306.lock().unwrap();
-
-            if floor > elevator.current_floor {
-                elevator.current_direction = Up;
-                ssd1306.update_direction(elevator.elevator, Up);
-            } else {
-                elevator.current_direction = Down;
-                ssd1306.update_direction(elevator.elevator, Down);
-            }
+            elevator.current_direction = Down;
+            display.update_direction(elevator.elevator, Down)?;
+        } else {
+            elevator.current_direction = Up;
+            display.update_direction(elevator.elevator, Up)?;
         }
 
-        let floors_range = floors_range.read().unwrap();
+        elevator.publish();
+    }
+
+    let mut target = center(elevator, floor)?;
+    let mut last_position = None;
+
+    // The integral and derivative of the previous trip mean nothing for this one
+    elevator.pid.reset();
+
+    let mut arrived = false;
+    let mut potency = 0.0;
+    let mut braking: Option<EmergencyStop> = None;
+    // Reference followed by the car and when it was planned
+    let mut trajectory: Option<(Trajectory, Instant)> = None;
+
+    while !elevator.out_of_service.load(Relaxed) {
+        let raw_position = esp32.lock().unwrap().get_encoder_value(elevator.encoder)?;
+        track_drift(elevator, raw_position);
+
+        let current_position = elevator.drift.correct(raw_position);
+        let now = Instant::now();
+        let mut retargeted = false;
 
-        let target = match floor {
-            Floor::Ground => floors_range.ground,
-            Floor::First => floors_range.first,
-            Floor::Second => floors_range.second,
-            Floor::Third => floors_range.third,
-            Floor::Undefined => unreachable!(),
+        let speed = last_position.map_or(0, |last: i32| (current_position - last).abs());
+        last_position = Some(current_position);
+
+        let horizon = match elevator.current_direction {
+            Down => current_position - speed * STOPPING_PERIODS,
+            _ => current_position + speed * STOPPING_PERIODS,
         };
 
-        while match floor {
-            Floor::Ground => &elevator.sensors.ground_sensor_pin,
-            Floor::First => &elevator.sensors.first_sensor_pin,
-            Floor::Second => &elevator.sensors.second_sensor_pin,
-            Floor::Third => &elevator.sensors.third_sensor_pin,
-            Floor::Undefined => unreachable!(),
+        if braking.is_none() && emergency.load(Relaxed) {
+            let stopping = braking.insert(EmergencyStop::new(
+                elevator.emergency_stop,
+                potency,
+                current_position,
+                now,
+            ));
+
+            // A standing car has nothing to slow down
+            let leveling = match stopping.profile() {
+                StopProfile::Soft if potency > 0.0 => emergency_stop::landing_ahead(
+                    &elevator.landings,
+                    elevator.current_direction,
+                    horizon,
+                ),
+                _ => None,
+            };
+
+            let Some(leveling) = leveling else {
+                break;
+            };
+
+            println!(
+                "Elevator {} in emergency, leveling at {}.",
+                elevator.elevator,
+                elevator.building.name(Some(leveling))
+            );
+
+            floor = leveling;
+            target = center(elevator, floor)?;
+            retargeted = true;
         }
-        .is_low()
-            && !emergency.load(Relaxed)
-        {
-            let current_position = esp32.lock().unwrap().get_encoder_value(elevator.encoder);
 
-            let (pid, direction) = elevator.pid.get_control_signal(current_position, target);
+        // Calls registered during the trip for a landing on the way are answered first
+        let stop = match braking {
+            Some(_) => None,
+            None => elevator.calls.read().unwrap().stop_before(
+                floor,
+                elevator.current_direction,
+                &elevator.landings,
+                horizon,
+            ),
+        };
 
-            elevator.engine_control.set_direction(direction);
-            elevator.engine_control.set_potency(pid.max(0.05));
+        if let Some(stop) = stop {
+            println!(
+                "Elevator {} stopping at {} on the way to {}.",
+                elevator.elevator,
+                elevator.building.name(Some(stop)),
+                elevator.building.name(Some(floor))
+            );
 
-            if let Ok(mut esp32) = esp32.try_lock() {
-                esp32.send_control_signal(elevator.encoder, (pid * 100.0) as i32);
-            }
+            floor = stop;
+            target = center(elevator, floor)?;
+            retargeted = true;
+        }
 
-            let current_floor = if current_position < floors_range.first - 100 {
-                Floor::Ground
-            } else if current_position < floors_range.second - 100 {
-                Floor::First
-            } else if current_position < floors_range.third - 100 {
-                Floor::Second
-            } else {
-                Floor::Third
+        // A new target is planned from where the reference stands, to keep the car moving smoothly
+        if trajectory.is_none() || retargeted {
+            let from = match &trajectory {
+                Some((trajectory, planned)) => trajectory.at(now - *planned),
+                None => Reference {
+                    position: current_position as f64,
+                    velocity: 0.0,
+                },
             };
 
-            if current_floor != elevator.current_floor {
-                elevator.current_floor = current_floor;
+            let planned = Trajectory::from_motion(from, target, &elevator.trajectory);
 
-                ssd1306
-                    .lock()
-                    .unwrap()
-                    .update_floor(elevator.elevator, current_floor);
-            }
+            println!(
+                "Elevator {} reaching {} in {:.1} s.",
+                elevator.elevator,
+                elevator.building.name(Some(floor)),
+                planned.duration().as_secs_f64()
+            );
 
-            thread::sleep(Duration::from_millis(100));
+            trajectory = Some((planned, now));
+        }
+
+        let position = elevator.landings.locate(current_position);
+
+        if position == CarPosition::AtFloor(floor) {
+            arrived = true;
+            break;
+        }
+
+        if !elevator.doors.is_closed() {
+            return Err(Error::Interlock(format!(
+                "elevator {} would move with the doors {:?}",
+                elevator.elevator,
+                elevator.doors.state()
+            )));
+        }
+
+        let (trajectory, planned) = trajectory.as_ref().unwrap();
+        let elapsed = now - *planned;
+        let reference = trajectory.at(elapsed);
+
+        let (pid, pid_direction) = elevator
+            .pid
+            .get_control_signal(current_position, reference.position.round() as i32);
+
+        let (direction, drive) = if elapsed < trajectory.duration() {
+            // The reference speed drives the motor, the controller corrects the position around it
+            let feedback = match pid_direction {
+                Down => -pid,
+                _ => pid,
+            };
+            let drive = reference.velocity / elevator.trajectory.full_speed + feedback;
+
+            (if drive < 0.0 { Down } else { Up }, drive.abs().min(1.0))
+        } else if position == CarPosition::LevelingZone(floor) {
+            // Creep through the leveling zone so the car stops level instead of crossing the band
+            (pid_direction, pid.clamp(MIN_POTENCY, LEVELING_POTENCY))
+        } else {
+            (pid_direction, pid.max(MIN_POTENCY))
+        };
+
+        potency = drive;
+
+        // A soft stop sheds speed at its own rate, yet keeps enough to reach the landing
+        if let Some(braking) = &mut braking {
+            potency = potency.min(braking.limit(now).max(MIN_POTENCY));
         }
 
-        elevator.engine_control.set_direction(Stop);
-        elevator.engine_control.set_potency(0.0);
+        elevator.engine_control.set_direction(direction)?;
+        elevator.engine_control.set_potency(potency)?;
+
+        if let Ok(mut esp32) = esp32.try_lock() {
+            esp32.send_control_signal(elevator.encoder, (potency * 100.0) as i32)?;
+        }
+
+        let current_floor = elevator.landings.nearest(current_position);
+
+        if current_floor != elevator.current_floor {
+            elevator.current_floor = current_floor;
+            elevator.publish();
+
+            display
+                .lock()
+                .unwrap()
+                .update_floor(elevator.elevator, elevator.building.name(current_floor))?;
+        }
 
-        ssd1306
-            .lock()
-            .unwrap()
-            .update_direction(elevator.elevator, Stop);
+        thread::sleep(Duration::from_millis(100));
     }
 
-    let buttons_to_deactivate = Button::get_buttons(elevator.elevator, floor);
+    elevator.engine_control.set_direction(Stop)?;
+    elevator.engine_control.set_potency(0.0)?;
+
+    if let Some(braking) = braking {
+        let position = standstill(esp32, elevator)?;
+        let (distance, time) = braking.measure(position, Instant::now());
+
+        println!(
+            "Elevator {} emergency stop ({:?}): {} ticks in {:.1} s.",
+            elevator.elevator,
+            braking.profile(),
+            distance,
+            time.as_secs_f64()
+        );
+    }
+
+    display
+        .lock()
+        .unwrap()
+        .update_direction(elevator.elevator, Stop)?;
+
+    // The trip resumes once the link is back, the calls and their lamps are kept
+    if !arrived {
+        return Ok(false);
+    }
+
+    answer(esp32, elevator, floor)?;
+
+    Ok(true)
+}
+
+// Serves the calls the car answers at the landing and turns off their lamps, a hall call the other way stays lit
+fn answer(
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    elevator: &mut MutexGuard<ElevatorState>,
+    floor: Floor,
+) -> Result<()> {
+    let served = {
+        let state = &mut **elevator;
+        let mut calls = state.calls.write().unwrap();
+        let leaving = calls.departure(floor, state.current_direction);
+
+        state.current_direction = leaving;
+        calls.serve(floor, leaving)
+    };
+
+    elevator.publish();
+
+    clear_lamps(esp32, elevator, served)
+}
+
+// Gives up the calls of a landing the car cannot reach, their lamps must not promise a car
+fn drop_calls(
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    elevator: &mut MutexGuard<ElevatorState>,
+    floor: Floor,
+) {
+    let dropped = elevator.calls.write().unwrap().serve(floor, Stop);
+
+    if let Err(e) = clear_lamps(esp32, elevator, dropped) {
+        eprintln!(
+            "Couldn't turn off the lamps of elevator {}: {}",
+            elevator.elevator, e
+        );
+    }
+}
+
+fn clear_lamps(
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    elevator: &MutexGuard<ElevatorState>,
+    calls: Vec<Call>,
+) -> Result<()> {
+    for call in calls {
+        for button in elevator.building.buttons_of(elevator.elevator, call) {
+            esp32.lock().unwrap().write_button(button, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Position once the car stopped moving, or after a second if it keeps creeping
+fn standstill(
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    elevator: &mut MutexGuard<ElevatorState>,
+) -> Result<i32> {
+    let mut position = esp32.lock().unwrap().get_encoder_value(elevator.encoder)?;
+
+    for _ in 0..10 {
+        thread::sleep(Duration::from_millis(100));
+
+        let last = position;
+        position = esp32.lock().unwrap().get_encoder_value(elevator.encoder)?;
+
+        if position == last {
+            break;
+        }
+    }
+
+    Ok(elevator.drift.correct(position))
+}
+
+// Realigns the encoder on every sensor edge crossed during the trip
+fn track_drift(elevator: &mut MutexGuard<ElevatorState>, raw_position: i32) {
+    let levels: Vec<bool> = (0..elevator.sensors.landings())
+        .map(|landing| elevator.sensors.is_active(landing))
+        .collect();
+    let state = &mut **elevator;
+
+    if let Some(correction) = state.drift.observe(raw_position, &levels, &state.landings) {
+        if correction != 0 {
+            println!(
+                "Elevator {} encoder corrected by {} ticks at {}.",
+                elevator.elevator, correction, raw_position
+            );
+        }
+    }
 
-    for button in buttons_to_deactivate {
-        esp32
-            .lock()
-            .unwrap()
-            .write_button(elevator.elevator, button, false);
+    if let Some(offset) = elevator.drift.warning() {
+        eprintln!(
+            "Elevator {} encoder drifted {} ticks since calibration, check the belt and the encoder coupling.",
+            elevator.elevator, offset
+        );
     }
 }
@@ -0,0 +1,93 @@
+use crate::common::Floor;
+use crate::elevator::calibration_file::{ElevatorCalibration, SensorBand};
+
+// Half band assumed for calibrations migrated without sensor edges, the margin used before bands were stored
+const DEFAULT_HALF_BAND: i32 = 100;
+
+/// Where the car stands relative to the calibrated landings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CarPosition {
+    /// Close enough to the center of the band to open the doors
+    AtFloor(Floor),
+    /// Inside the sensor band but not level yet
+    LevelingZone(Floor),
+    /// Outside every band, None below the lowest band or above the highest one
+    BetweenFloors {
+        below: Option<Floor>,
+        above: Option<Floor>,
+    },
+}
+
+/// Sensor band of every landing of one shaft, from the lowest one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Landings {
+    bands: Vec<SensorBand>,
+}
+
+impl Landings {
+    pub fn new(bands: Vec<SensorBand>) -> Self {
+        Landings { bands }
+    }
+
+    pub fn from_calibration(calibration: &ElevatorCalibration) -> Self {
+        let bands = calibration.bands.clone().unwrap_or_else(|| {
+            calibration
+                .positions
+                .iter()
+                .map(|center| SensorBand {
+                    lower: center - DEFAULT_HALF_BAND,
+                    upper: center + DEFAULT_HALF_BAND,
+                })
+                .collect()
+        });
+
+        Landings::new(bands)
+    }
+
+    pub fn band(&self, floor: Floor) -> Option<SensorBand> {
+        self.bands.get(floor.index()).copied()
+    }
+
+    /// Encoder count where the car is level with the landing
+    pub fn center(&self, floor: Floor) -> Option<i32> {
+        self.band(floor).map(|band| band.center())
+    }
+
+    pub fn locate(&self, position: i32) -> CarPosition {
+        for (index, band) in self.bands.iter().enumerate() {
+            let floor = Floor(index);
+
+            if position < band.lower {
+                return CarPosition::BetweenFloors {
+                    below: index.checked_sub(1).map(Floor),
+                    above: Some(floor),
+                };
+            }
+
+            if position <= band.upper {
+                // Level within the middle half of the band
+                let tolerance = (band.upper - band.lower) / 4;
+
+                return if (position - band.center()).abs() <= tolerance {
+                    CarPosition::AtFloor(floor)
+                } else {
+                    CarPosition::LevelingZone(floor)
+                };
+            }
+        }
+
+        CarPosition::BetweenFloors {
+            below: self.bands.len().checked_sub(1).map(Floor),
+            above: None,
+        }
+    }
+
+    /// Landing whose center is the closest to the position, None before calibration
+    pub fn nearest(&self, position: i32) -> Option<Floor> {
+        self.bands
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, band)| (position - band.center()).abs())
+            .map(|(index, _)| Floor(index))
+    }
+}
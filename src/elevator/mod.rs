@@ -1,5 +1,18 @@
-mod calibration_control;
+pub mod building;
+pub mod calibration_control;
+pub mod calibration_file;
+pub mod calls;
+pub mod dispatcher;
+pub mod doors;
+pub mod drift;
 pub mod elevator_control;
+pub mod emergency_stop;
+pub mod failsafe_control;
 mod floor_control;
+pub mod landings;
 mod panel_control;
 mod temperature_control;
+pub mod trajectory;
+
+#[cfg(test)]
+mod tests;
@@ -1,6 +1,12 @@
-use crate::common::{Elevator, Floor};
-use crate::uart::esp32::{Button, Esp32};
-use std::collections::VecDeque;
+use crate::common::Elevator;
+use crate::elevator::building::Building;
+use crate::elevator::calls::{Call, CallSet};
+use crate::elevator::dispatcher::Dispatcher;
+use crate::elevator::doors::DoorCommand;
+use crate::error::Result;
+use crate::gpio::motor_safety::WorkerGuard;
+use crate::uart::esp32::Esp32;
+use crate::uart::transport::Transport;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::Relaxed;
 use std::sync::{Arc, Mutex, RwLock};
@@ -8,78 +14,158 @@ use std::thread;
 use std::time::Duration;
 use stoppable_thread::StoppableHandle;
 
-pub fn start(
-    esp32: Arc<Mutex<Esp32>>,
-    queues: (Arc<RwLock<VecDeque<Floor>>>, Arc<RwLock<VecDeque<Floor>>>),
-    emergency: (Arc<AtomicBool>, Arc<AtomicBool>),
+type Calls = Arc<RwLock<CallSet>>;
+
+/// What the panel of a car hands to its floor thread.
+pub struct CarPanel {
+    pub calls: Calls,
+    pub emergency: Arc<AtomicBool>,
+    pub door_command: Arc<Mutex<Option<DoorCommand>>>,
+}
+
+/// Reads the panel of each car, given in the order of the building topology
+///
+/// Hall calls go through the dispatcher when there is one.
+pub fn start<T: Transport + 'static>(
+    esp32: Arc<Mutex<Esp32<T>>>,
+    building: Arc<Building>,
+    cars: Vec<CarPanel>,
+    dispatcher: Option<Dispatcher>,
 ) -> StoppableHandle<()> {
     stoppable_thread::spawn(move |stopped| {
+        let _guard = WorkerGuard::new("Panel control");
+
         while !stopped.get() {
-            if !emergency.0.load(Relaxed) {
-                let registers = esp32.lock().unwrap().read_all_buttons(Elevator::One);
+            for (elevator, car) in building.elevators().zip(&cars) {
+                let panel = Panel {
+                    esp32: &esp32,
+                    building: &building,
+                    dispatcher: dispatcher.as_ref(),
+                    elevator,
+                };
 
-                for (button, value) in registers {
-                    if !value {
-                        continue;
-                    }
+                if let Err(e) = panel.read(car) {
+                    eprintln!("Couldn't read panel of elevator {}: {}", elevator, e);
+                }
+            }
 
-                    if button == Button::Emergency1 {
-                        emergency.0.store(true, Relaxed);
+            thread::sleep(Duration::from_millis(500));
+        }
+    })
+}
 
-                        queues.0.write().unwrap().clear();
+struct Panel<'a, T: Transport> {
+    esp32: &'a Arc<Mutex<Esp32<T>>>,
+    building: &'a Building,
+    dispatcher: Option<&'a Dispatcher>,
+    elevator: Elevator,
+}
 
-                        let mut state = [false; 11];
-                        state[6] = true;
+impl<T: Transport> Panel<'_, T> {
+    fn read(&self, car: &CarPanel) -> Result<()> {
+        let wiring = self.building.car(self.elevator);
+        let (first, last) = wiring.block();
+        let registers = self
+            .esp32
+            .lock()
+            .unwrap()
+            .read_buttons_in_range(first, last)?;
 
-                        esp32
-                            .lock()
-                            .unwrap()
-                            .write_all_buttons(Elevator::One, &state);
-                    } else {
-                        if let Some(floor) = button.into_floor(Elevator::One) {
-                            let mut queue = queues.0.write().unwrap();
+        for (button, value) in registers {
+            if !value {
+                continue;
+            }
 
-                            if !queue.contains(&floor) {
-                                queue.push_back(floor);
-                            }
-                        }
-                    }
+            if button == wiring.emergency {
+                // Its lamp stays lit until the operator resets the car
+                if car.emergency.load(Relaxed) {
+                    continue;
                 }
-            }
 
-            if !emergency.1.load(Relaxed) {
-                let registers = esp32.lock().unwrap().read_all_buttons(Elevator::Two);
+                car.emergency.store(true, Relaxed);
+
+                let pending = car.calls.write().unwrap().take();
+
+                let state = wiring.lamps(&[wiring.emergency]);
+
+                self.esp32
+                    .lock()
+                    .unwrap()
+                    .write_button_in_range(first, last, &state)?;
 
-                for (button, value) in registers {
-                    if !value {
-                        continue;
+                // The other cars take over the hall calls, the emergency car is left out by its flag
+                if let Some(dispatcher) = self.dispatcher {
+                    for call in pending
+                        .into_iter()
+                        .filter(|call| matches!(call, Call::Hall(..)))
+                    {
+                        dispatch(self.esp32, self.building, dispatcher, call, None)?;
                     }
+                }
+            } else if let Some(command) = wiring.door_command_of(button) {
+                *car.door_command.lock().unwrap() = Some(command);
 
-                    if button == Button::Emergency2 {
-                        emergency.1.store(true, Relaxed);
+                // Door buttons have no lamp, release them to take the next press
+                self.esp32.lock().unwrap().write_button(button, false)?;
+            } else if let Some(call) = wiring.call_of(button) {
+                self.register(car, call)?;
+            }
+        }
 
-                        queues.1.write().unwrap().clear();
+        Ok(())
+    }
 
-                        let mut state = [false; 11];
-                        state[6] = true;
+    fn register(&self, car: &CarPanel, call: Call) -> Result<()> {
+        if let (Call::Hall(..), Some(dispatcher)) = (call, self.dispatcher) {
+            return dispatch(self.esp32, self.building, dispatcher, call, None);
+        }
 
-                        esp32
-                            .lock()
-                            .unwrap()
-                            .write_all_buttons(Elevator::Two, &state);
-                    } else {
-                        if let Some(floor) = button.into_floor(Elevator::Two) {
-                            let mut queue = queues.1.write().unwrap();
+        // A car in emergency takes no calls, the pressed button is turned off again
+        if car.emergency.load(Relaxed) {
+            let wiring = self.building.car(self.elevator);
 
-                            if !queue.contains(&floor) {
-                                queue.push_back(floor);
-                            }
-                        }
-                    }
-                }
+            if let Some(button) = wiring.button_of(call) {
+                self.esp32.lock().unwrap().write_button(button, false)?;
             }
 
-            thread::sleep(Duration::from_millis(500));
+            return Ok(());
         }
-    })
+
+        car.calls.write().unwrap().insert(call);
+
+        Ok(())
+    }
+}
+
+/// Gives a hall call to the car arriving first and lights it on every panel, unless a car already has it
+pub(super) fn dispatch(
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    building: &Building,
+    dispatcher: &Dispatcher,
+    call: Call,
+    excluded: Option<Elevator>,
+) -> Result<()> {
+    if dispatcher.is_pending(call) {
+        return Ok(());
+    }
+
+    let landing = building.name(Some(call.floor()));
+
+    let Some(elevator) = dispatcher.assign(call, excluded) else {
+        eprintln!("No elevator available for the hall call at {}.", landing);
+        return Ok(());
+    };
+
+    println!(
+        "Hall call at {} assigned to elevator {}.",
+        landing, elevator
+    );
+
+    let mut esp32 = esp32.lock().unwrap();
+
+    for button in building.buttons_of(elevator, call) {
+        esp32.write_button(button, true)?;
+    }
+
+    Ok(())
 }
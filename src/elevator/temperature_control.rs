@@ -1,51 +1,81 @@
 use crate::common::Elevator;
-use crate::i2c::{bme280::BME280, ssd1306::SSD1306};
+use crate::error::Result;
+use crate::gpio::motor_safety::WorkerGuard;
+use crate::i2c::{bme280::BME280, display::StatusDisplay};
 use crate::uart::esp32::Esp32;
+use crate::uart::transport::Transport;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 use stoppable_thread::StoppableHandle;
 
-pub fn start(esp32: Arc<Mutex<Esp32>>, ssd1306: Arc<Mutex<SSD1306>>) -> StoppableHandle<()> {
+/// Reports the temperature of every car with a thermometer, given by its I2C address in fleet order
+pub fn start<T: Transport + 'static, D: StatusDisplay + 'static>(
+    esp32: Arc<Mutex<Esp32<T>>>,
+    display: Arc<Mutex<D>>,
+    thermometers: Vec<Option<u8>>,
+) -> StoppableHandle<()> {
     stoppable_thread::spawn(move |stopped| {
-        let mut bme280 = BME280::new();
+        let _guard = WorkerGuard::new("Temperature control");
 
-        let mut temperature_1 = 0.0;
-        let mut temperature_2 = 0.0;
-
-        while !stopped.get() {
-            let current_temperature_1 = bme280.measure_temperature(Elevator::One);
-            let current_temperature_2 = bme280.measure_temperature(Elevator::Two);
-
-            if current_temperature_1 != temperature_1 {
-                temperature_1 = current_temperature_1;
-
-                esp32
-                    .lock()
-                    .unwrap()
-                    .send_temp(Elevator::One, temperature_1);
-
-                ssd1306
-                    .lock()
-                    .unwrap()
-                    .update_temperature(Elevator::One, temperature_1);
+        let mut bme280 = match BME280::new(&thermometers) {
+            Ok(bme280) => bme280,
+            Err(e) => {
+                eprintln!("Temperature monitoring disabled: {}", e);
+                return;
             }
+        };
 
-            if current_temperature_2 != temperature_2 {
-                temperature_2 = current_temperature_2;
+        let elevators: Vec<_> = (0..thermometers.len())
+            .map(Elevator)
+            .filter(|elevator| bme280.has_sensor(*elevator))
+            .collect();
 
-                esp32
-                    .lock()
-                    .unwrap()
-                    .send_temp(Elevator::Two, temperature_2);
+        let mut temperatures = vec![0.0; thermometers.len()];
 
-                ssd1306
-                    .lock()
-                    .unwrap()
-                    .update_temperature(Elevator::Two, temperature_2);
+        while !stopped.get() {
+            for elevator in &elevators {
+                if let Err(e) = update(
+                    &esp32,
+                    &display,
+                    &mut bme280,
+                    *elevator,
+                    &mut temperatures[elevator.index()],
+                ) {
+                    eprintln!(
+                        "Couldn't update temperature of elevator {}: {}",
+                        elevator, e
+                    );
+                }
             }
 
             thread::sleep(Duration::from_secs(1));
         }
     })
 }
+
+fn update(
+    esp32: &Arc<Mutex<Esp32<impl Transport>>>,
+    display: &Arc<Mutex<impl StatusDisplay>>,
+    bme280: &mut BME280,
+    elevator: Elevator,
+    temperature: &mut f32,
+) -> Result<()> {
+    let current_temperature = bme280.measure_temperature(elevator)?;
+
+    if current_temperature != *temperature {
+        esp32
+            .lock()
+            .unwrap()
+            .send_temp(elevator, current_temperature)?;
+
+        display
+            .lock()
+            .unwrap()
+            .update_temperature(elevator, current_temperature)?;
+
+        *temperature = current_temperature;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1474 @@
+use crate::common::{
+    Direction::{self, Down, Stop, Up},
+    Elevator, Floor,
+};
+use crate::elevator::building::{Building, MotorPins};
+use crate::elevator::calibration_control::{self, CalibrationLimits};
+use crate::elevator::calibration_file::{self, ElevatorCalibration, SensorBand};
+use crate::elevator::calls::{Call, CallSet};
+use crate::elevator::dispatcher::{time_to_arrive, CarStatus, Dispatcher};
+use crate::elevator::doors::{DoorCommand, DoorConfig, DoorState, Doors};
+use crate::elevator::drift::DriftCorrection;
+use crate::elevator::elevator_control::ElevatorControl;
+use crate::elevator::emergency_stop::{self, EmergencyConfig, EmergencyStop, StopProfile};
+use crate::elevator::failsafe_control::{Failsafe, FailsafeConfig};
+use crate::elevator::landings::{CarPosition, Landings};
+use crate::elevator::trajectory::{Reference, Trajectory, TrajectoryLimits};
+use crate::error::{CalibrationError, Error};
+use crate::gpio::motor_driver::{MotorCommand, MotorLog};
+use crate::gpio::pid::PidGains;
+use crate::i2c::display::MemoryDisplay;
+use crate::sim::plant::PlantParameters;
+use crate::sim::rig::Rig;
+use crate::uart::esp32::{Button, Encoder, LinkHealth};
+use crate::uart::transport::MemoryTransport;
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use std::{env, fs, process, thread};
+
+#[test]
+fn failsafe_trips_and_recovers_after_grace() {
+    // Arrange
+    let mut failsafe = Failsafe::new(FailsafeConfig {
+        max_failures: 3,
+        grace_period: Duration::from_secs(5),
+    });
+
+    let start = Instant::now();
+    let failing = |failures| LinkHealth {
+        consecutive_failures: failures,
+        last_failure: Some(start),
+    };
+    let healthy = LinkHealth {
+        consecutive_failures: 0,
+        last_failure: Some(start),
+    };
+
+    // Act
+    let below_threshold = failsafe.update(failing(2), start);
+    let tripped = failsafe.update(failing(3), start);
+    let still_failing = failsafe.update(failing(4), start + Duration::from_secs(10));
+    let within_grace = failsafe.update(healthy, start + Duration::from_secs(4));
+    let recovered = failsafe.update(healthy, start + Duration::from_secs(5));
+
+    // Assert
+    assert_eq!(below_threshold, None);
+    assert_eq!(tripped, Some(true));
+    assert_eq!(still_failing, None);
+    assert_eq!(within_grace, None);
+    assert_eq!(recovered, Some(false));
+    assert!(!failsafe.is_out_of_service());
+}
+
+fn calibration(elevator: u8) -> ElevatorCalibration {
+    let bands = [
+        SensorBand {
+            lower: 900,
+            upper: 1100,
+        },
+        SensorBand {
+            lower: 8400,
+            upper: 8600,
+        },
+        SensorBand {
+            lower: 15900,
+            upper: 16100,
+        },
+        SensorBand {
+            lower: 23400,
+            upper: 23600,
+        },
+    ];
+
+    ElevatorCalibration {
+        elevator,
+        calibrated_at: 1_700_000_000,
+        positions: bands.iter().map(SensorBand::center).collect(),
+        bands: Some(bands.to_vec()),
+    }
+}
+
+#[test]
+fn calibration_file_round_trip() {
+    // Arrange
+    let calibrations = [calibration(1), calibration(2)];
+
+    // Act
+    let text = calibration_file::render(&calibrations).unwrap();
+    let parsed = calibration_file::parse(&text, 4, 2).unwrap();
+
+    // Assert
+    assert_eq!(parsed, calibrations.map(Some));
+}
+
+#[test]
+fn calibration_file_rejects_tampering() {
+    // Arrange
+    let text = calibration_file::render(&[calibration(1)]).unwrap();
+
+    let mut reversed = calibration(2);
+    reversed.positions = vec![16000, 8500, 1000, 23500];
+    reversed.bands = None;
+
+    // Act
+    let tampered = calibration_file::parse(&text.replace("8500", "8501"), 4, 2);
+    let unordered = calibration_file::parse(&calibration_file::render(&[reversed]).unwrap(), 4, 2);
+    let other_building = calibration_file::parse(&text, 6, 2);
+
+    // Assert
+    assert!(tampered.is_err());
+    assert!(unordered.is_err());
+    assert!(other_building.is_err());
+}
+
+#[test]
+fn calibration_sweep_result_validated() {
+    // Arrange
+    let sound = calibration(1);
+
+    // A sensor seen again while rising, its band swallowing the next landing
+    let mut overlapping = calibration(1);
+    overlapping.bands.as_mut().unwrap()[1].upper = 16000;
+
+    // Act
+    let accepted = sound.validate(4, 2);
+    let rejected = overlapping.validate(4, 2);
+
+    // Assert
+    assert!(accepted.is_ok());
+    assert!(matches!(
+        rejected,
+        Err(Error::Calibration(CalibrationError::Invalid(_)))
+    ));
+}
+
+#[test]
+fn calibration_file_migrates_legacy() {
+    // Arrange
+    let bytes: Vec<u8> = [1000, 8500, 16000, 23500]
+        .iter()
+        .flat_map(|tick: &i32| tick.to_le_bytes())
+        .collect();
+
+    // Act
+    let [first, second]: [_; 2] = calibration_file::parse_legacy(&bytes, 4, 2)
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let truncated = calibration_file::parse_legacy(&bytes[..10], 4, 2);
+    let doubled = calibration_file::parse_legacy(&bytes.repeat(2), 4, 2);
+
+    // Assert
+    let first = first.unwrap();
+    assert_eq!(first.elevator, 1);
+    assert_eq!(first.positions, [1000, 8500, 16000, 23500]);
+    assert_eq!(first.bands, None);
+    assert!(second.is_none());
+    assert!(truncated.is_err());
+    assert!(doubled.is_err());
+}
+
+#[test]
+fn landings_locate_car() {
+    // Arrange
+    let landings = Landings::from_calibration(&calibration(1));
+
+    // Act
+    let positions = [0, 1000, 1080, 1099, 4000, 8450, 23700].map(|tick| landings.locate(tick));
+
+    // Assert
+    assert_eq!(
+        positions,
+        [
+            CarPosition::BetweenFloors {
+                below: None,
+                above: Some(Floor(0))
+            },
+            CarPosition::AtFloor(Floor(0)),
+            CarPosition::LevelingZone(Floor(0)),
+            CarPosition::LevelingZone(Floor(0)),
+            CarPosition::BetweenFloors {
+                below: Some(Floor(0)),
+                above: Some(Floor(1))
+            },
+            CarPosition::AtFloor(Floor(1)),
+            CarPosition::BetweenFloors {
+                below: Some(Floor(3)),
+                above: None
+            },
+        ]
+    );
+    assert_eq!(landings.nearest(4000), Some(Floor(0)));
+    assert_eq!(landings.nearest(5000), Some(Floor(1)));
+}
+
+#[test]
+fn landings_without_bands_use_default_margin() {
+    // Arrange
+    let mut migrated = calibration(1);
+    migrated.bands = None;
+
+    // Act
+    let landings = Landings::from_calibration(&migrated);
+
+    // Assert
+    assert_eq!(landings.center(Floor(2)), Some(16000));
+    assert_eq!(landings.locate(15920), CarPosition::LevelingZone(Floor(2)));
+    assert_eq!(
+        landings.locate(15800),
+        CarPosition::BetweenFloors {
+            below: Some(Floor(1)),
+            above: Some(Floor(2))
+        }
+    );
+}
+
+#[test]
+fn drift_corrected_on_sensor_edges() {
+    // Arrange
+    let landings = Landings::from_calibration(&calibration(1));
+    let mut drift = DriftCorrection::new();
+    let sensors = |shaft: i32| {
+        [900..=1100, 8400..=8600, 15900..=16100, 23400..=23600].map(|band| band.contains(&shaft))
+    };
+
+    // Act: the belt slipped 300 ticks, the shaft is ahead of the encoder
+    let corrections: Vec<_> = (0..10)
+        .map(|step| 7900 + step * 100)
+        .map(|shaft| drift.observe(shaft - 300, &sensors(shaft), &landings))
+        .collect();
+
+    // Assert
+    assert_eq!(corrections.iter().flatten().sum::<i32>(), 300);
+    assert_eq!(drift.correct(8200), 8500);
+    assert_eq!(drift.warning(), Some(300));
+    assert_eq!(drift.warning(), None);
+}
+
+#[test]
+fn drift_ignores_edges_within_a_sample() {
+    // Arrange
+    let landings = Landings::from_calibration(&calibration(1));
+    let mut drift = DriftCorrection::new();
+
+    // Act: the ground sensor turns off somewhere between 1050 and 1150 going up
+    drift.observe(1050, &[true, false, false, false], &landings);
+    let correction = drift.observe(1150, &[false, false, false, false], &landings);
+
+    // Assert
+    assert_eq!(correction, Some(0));
+    assert_eq!(drift.offset(), 0);
+    assert_eq!(drift.warning(), None);
+}
+
+fn six_floors() -> Building {
+    let landings = ["S1", "T", "1", "2", "3", "4"]
+        .map(|name| format!("[[landings]]\nname = \"{}\"\n", name))
+        .concat();
+
+    let car = |encoder: u8, pins: &str, motor: [u8; 3]| {
+        let offset = encoder * 0xA0;
+        let buttons: String = (0..6u8)
+            .map(|landing| {
+                format!(
+                    "[[cars.buttons]]\nup = {}\ndown = {}\ncall = {}\n",
+                    offset + landing,
+                    offset + 6 + landing,
+                    offset + 12 + landing
+                )
+            })
+            .collect();
+
+        format!(
+            "[[cars]]\nencoder = {}\nsensor_pins = {}\nemergency = {}\n\
+             [cars.motor]\ndir1 = {}\ndir2 = {}\npotm = {}\n{}",
+            encoder,
+            pins,
+            offset + 18,
+            motor[0],
+            motor[1],
+            motor[2],
+            buttons
+        )
+    };
+
+    Building::parse(&format!(
+        "{}{}{}",
+        landings,
+        car(0, "[2, 3, 4, 5, 6, 7]", [20, 21, 14]),
+        car(1, "[8, 9, 10, 11, 12, 13]", [19, 26, 15])
+    ))
+    .unwrap()
+}
+
+#[test]
+fn building_maps_buttons_to_landings() {
+    // Arrange
+    let building = six_floors();
+    let car = building.car(Elevator(1));
+
+    // Act
+    let (first, last) = car.block();
+    let lamps = car.lamps(&[car.emergency]);
+
+    // Assert
+    assert_eq!(building.floors().count(), 6);
+    assert_eq!(building.name(Some(Floor(0))), "S1");
+    assert_eq!(building.name(None), "?");
+    assert_eq!((first, last), (Button(0xA0), Button(0xB2)));
+    assert_eq!(car.call_of(Button(0xAD)), Some(Call::Car(Floor(1))));
+    assert_eq!(car.call_of(Button(0xA7)), Some(Call::Hall(Floor(1), Down)));
+    assert_eq!(car.call_of(car.emergency), None);
+    assert_eq!(
+        car.button_of(Call::Hall(Floor(5), Down)),
+        Some(Button(0xAB))
+    );
+    assert_eq!(car.button_of(Call::Car(Floor(5))), Some(Button(0xB1)));
+    assert_eq!(lamps.len(), 19);
+    assert_eq!(lamps.iter().filter(|lit| **lit).count(), 1);
+    assert!(lamps[18]);
+}
+
+#[test]
+fn building_rejects_inconsistent_wiring() {
+    // Arrange
+    let lab = toml::to_string(&Building::default()).unwrap();
+
+    let mut missing_sensor = Building::default();
+    missing_sensor.cars[1].sensor_pins.pop();
+
+    let mut shared_register = Building::default();
+    shared_register.cars[1].emergency = shared_register.cars[0].emergency;
+
+    // The first block spans 0x00 to 0xB0, over the other car's 0xA0 to 0xAA, once its emergency button moves up
+    let mut interleaved = Building::default();
+    interleaved.cars[0].emergency = Button(0xB0);
+
+    // A single car from 0x00 to 0xFF has 256 registers
+    let mut whole_range = Building::default();
+    whole_range.cars.truncate(1);
+    whole_range.cars[0].emergency = Button(0xFF);
+
+    // Act
+    let parsed = Building::parse(&lab);
+    let missing_sensor = Building::parse(&toml::to_string(&missing_sensor).unwrap());
+    let shared_register = Building::parse(&toml::to_string(&shared_register).unwrap());
+    let interleaved = Building::parse(&toml::to_string(&interleaved).unwrap());
+    let whole_range = Building::parse(&toml::to_string(&whole_range).unwrap());
+
+    // Assert
+    assert_eq!(parsed.unwrap(), Building::default());
+    assert!(missing_sensor.is_err());
+    assert!(shared_register.is_err());
+    assert!(interleaved.is_err());
+    assert!(whole_range.is_err());
+}
+
+#[test]
+fn building_tunes_doors_stops_trips_and_gains_per_car() {
+    // Arrange
+    let car = |encoder: u8, pins: &str, tuning: &str| {
+        format!(
+            "[[cars]]\nencoder = {0}\nsensor_pins = {1}\nemergency = {2}\n\
+             [cars.motor]\ndir1 = {3}\ndir2 = {4}\npotm = {5}\n{6}\
+             [[cars.buttons]]\nup = {7}\ncall = {8}\n\
+             [[cars.buttons]]\ndown = {9}\ncall = {10}\n",
+            encoder,
+            pins,
+            encoder * 0x10,
+            encoder * 3 + 2,
+            encoder * 3 + 3,
+            encoder * 3 + 4,
+            tuning,
+            encoder * 0x10 + 1,
+            encoder * 0x10 + 2,
+            encoder * 0x10 + 3,
+            encoder * 0x10 + 4,
+        )
+    };
+    let landings = "[[landings]]\nname = \"T\"\n[[landings]]\nname = \"1\"\n";
+    let tuned = "[cars.doors]\ndwell = 4.5\n\
+                 [cars.emergency_stop]\nprofile = \"soft\"\ndeceleration = 0.8\n\
+                 [cars.trajectory]\nspeed = 2000\n\
+                 [cars.pid]\nkd = 0.00002\n";
+    let text = format!(
+        "{}{}{}",
+        landings,
+        car(0, "[20, 21]", ""),
+        car(1, "[22, 23]", tuned)
+    );
+
+    // Act
+    let building = Building::parse(&text);
+    let malformed = Building::parse(&text.replace("dwell = 4.5", "dwell = \"long\""));
+    let instant = Building::parse(&text.replace("dwell = 4.5", "dwell = 0"));
+    let backwards = Building::parse(&text.replace("dwell = 4.5", "travel = -1.0"));
+    let stalled = Building::parse(&text.replace("deceleration = 0.8", "deceleration = 0"));
+    let jerkless = Building::parse(&text.replace("speed = 2000", "jerk = 0"));
+    let unstable = Building::parse(&text.replace("kd = 0.00002", "kd = -0.00002"));
+    let unfiltered = Building::parse(&text.replace("kd = 0.00002", "derivative_filter = nan"));
+
+    // Assert
+    let building = building.unwrap();
+    let (lab, tuned) = (building.car(Elevator(0)), building.car(Elevator(1)));
+
+    assert_eq!(lab.doors, DoorConfig::default());
+    assert_eq!(lab.emergency_stop, EmergencyConfig::default());
+    assert_eq!(lab.trajectory, TrajectoryLimits::default());
+    assert_eq!(lab.pid, PidGains::default());
+    assert_eq!(tuned.doors.dwell, Duration::from_secs_f64(4.5));
+    assert_eq!(tuned.doors.travel, DoorConfig::default().travel);
+    assert_eq!(tuned.emergency_stop.profile, StopProfile::Soft);
+    assert_eq!(tuned.emergency_stop.deceleration, 0.8);
+    assert_eq!(tuned.trajectory.speed, 2000.0);
+    assert_eq!(tuned.pid.kd, 0.00002);
+    assert_eq!(tuned.pid.kp, PidGains::default().kp);
+    assert!(malformed.is_err());
+    assert!(instant.is_err());
+    assert!(backwards.is_err());
+    assert!(stalled.is_err());
+    assert!(jerkless.is_err());
+    assert!(unstable.is_err());
+    assert!(unfiltered.is_err());
+}
+
+#[test]
+fn building_describes_a_larger_fleet() {
+    // Arrange
+    let mut building = Building::default();
+    let shift = |button: Button| Button(button.0 + 0x20);
+
+    let mut third = building.cars[1].clone();
+    third.encoder = Encoder(2);
+    third.motor = MotorPins {
+        dir1: 4,
+        dir2: 5,
+        potm: 16,
+    };
+    third.thermometer = None;
+    third.sensor_pins = vec![7, 8, 9, 10];
+    third.emergency = shift(third.emergency);
+
+    for buttons in &mut third.buttons {
+        buttons.up = buttons.up.map(shift);
+        buttons.down = buttons.down.map(shift);
+        buttons.call = shift(buttons.call);
+    }
+
+    building.cars.push(third);
+
+    let mut shared_encoder = building.clone();
+    shared_encoder.cars[2].encoder = Encoder(0);
+
+    let text = calibration_file::render(&[calibration(3)]).unwrap();
+
+    // Act
+    let parsed = Building::parse(&toml::to_string(&building).unwrap());
+    let shared_encoder = Building::parse(&toml::to_string(&shared_encoder).unwrap());
+    let fleet = calibration_file::parse(&text, 4, 3);
+    let smaller_fleet = calibration_file::parse(&text, 4, 2);
+
+    // Assert
+    let parsed = parsed.unwrap();
+    assert_eq!(parsed.elevators().count(), 3);
+    assert_eq!(
+        parsed.car(Elevator(2)).block(),
+        (Button(0xC0), Button(0xCA))
+    );
+    assert!(shared_encoder.is_err());
+    assert_eq!(fleet.unwrap(), [None, None, Some(calibration(3))]);
+    assert!(smaller_fleet.is_err());
+}
+
+// Shaft a few times faster than the lab rig, so a trip or a sweep takes a few seconds
+fn fast_plant() -> PlantParameters {
+    PlantParameters {
+        motor_gain: 6000.0,
+        landings: vec![0.2, 1.2, 2.2, 3.2],
+        sensor_band: 0.24,
+        shaft_height: 3.5,
+        ..PlantParameters::default()
+    }
+}
+
+// Encoder count of a landing of the fast plant
+fn landing(floor: usize) -> i32 {
+    let plant = fast_plant();
+
+    (plant.landings[floor] * plant.ticks_per_metre).round() as i32
+}
+
+// Sensor band of a landing of the fast plant, as a perfect sweep would find it
+fn band(floor: usize) -> SensorBand {
+    let plant = fast_plant();
+    let half = (plant.sensor_band / 2.0 * plant.ticks_per_metre).round() as i32;
+
+    SensorBand {
+        lower: landing(floor) - half,
+        upper: landing(floor) + half,
+    }
+}
+
+// The fast plant runs ~12700 ticks/s at full duty cycle
+fn fast_trajectory() -> TrajectoryLimits {
+    TrajectoryLimits {
+        full_speed: 12700.0,
+        speed: 3000.0,
+        acceleration: 6000.0,
+        jerk: 30000.0,
+    }
+}
+
+// The lab rig with trips and doors sized for the fast plant
+fn fast_building() -> Building {
+    let mut building = Building::default();
+
+    for car in &mut building.cars {
+        car.trajectory = fast_trajectory();
+        car.doors = DoorConfig {
+            travel: Duration::from_millis(200),
+            dwell: Duration::from_millis(300),
+        };
+    }
+
+    building
+}
+
+/// Calibration file of one test, removed once the test ends.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!(
+            "fse-trab-2-{}-{}.toml",
+            process::id(),
+            COUNT.fetch_add(1, Relaxed)
+        );
+        let path = env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+
+        TempFile(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn start_fleet(
+    building: &Building,
+    rig: &Rig,
+    file: &TempFile,
+) -> ElevatorControl<MemoryTransport, MemoryDisplay> {
+    let display = MemoryDisplay::new(building.cars.len());
+    let mut control =
+        ElevatorControl::with_parts(building.clone(), rig.esp32(), display, rig.cars()).unwrap();
+
+    control.set_calibration_file(&file.0);
+    control.init().unwrap();
+
+    control
+}
+
+// Every cabin resting at the given height (m), with the calibration a perfect sweep of the shaft would save
+fn calibrated_fleet(
+    building: &Building,
+    height: f64,
+) -> (
+    Rig,
+    ElevatorControl<MemoryTransport, MemoryDisplay>,
+    TempFile,
+) {
+    let rig = Rig::new(building, fast_plant());
+    let file = TempFile::new();
+
+    let calibrations: Vec<_> = building
+        .elevators()
+        .map(|elevator| {
+            let bands: Vec<_> = (0..4).map(band).collect();
+
+            ElevatorCalibration {
+                elevator: elevator.index() as u8 + 1,
+                calibrated_at: 0,
+                positions: bands.iter().map(SensorBand::center).collect(),
+                bands: Some(bands),
+            }
+        })
+        .collect();
+    calibration_control::write_calibration(&file.0, &calibrations).unwrap();
+
+    for elevator in building.elevators() {
+        rig.set_position(elevator, height);
+    }
+
+    let control = start_fleet(building, &rig, &file);
+
+    (rig, control, file)
+}
+
+// Button inside the car calling it to the landing, as wired in the lab
+fn car_call(elevator: Elevator, floor: usize) -> Button {
+    Building::default().car(elevator).buttons[floor].call
+}
+
+fn emergency(elevator: Elevator) -> Button {
+    Building::default().car(elevator).emergency
+}
+
+fn wait_until(timeout: Duration, condition: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+
+    while !condition() {
+        if start.elapsed() > timeout {
+            return false;
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    true
+}
+
+#[test]
+fn fleet_serves_a_call_on_the_plant() {
+    // Arrange
+    let (rig, mut control, _file) = calibrated_fleet(&fast_building(), 0.2);
+    let other = rig.position(Elevator(1));
+
+    // Act
+    rig.press(car_call(Elevator(0), 2));
+    let served = wait_until(Duration::from_secs(10), || {
+        !rig.is_lit(car_call(Elevator(0), 2))
+    });
+    control.stop().unwrap();
+
+    // Assert
+    assert!(served);
+    assert!(rig.log(Elevator(0)).is_stopped());
+    assert!((rig.position(Elevator(0)) - landing(2)).abs() <= 300);
+    assert_eq!(rig.position(Elevator(1)), other);
+}
+
+#[test]
+fn fleet_calibrates_each_car_in_its_own_shaft() {
+    // Arrange
+    // A stronger motor shortens the sweep, which only reads the sensors
+    let sweep = PlantParameters {
+        motor_gain: 12000.0,
+        ..fast_plant()
+    };
+    let offset = PlantParameters {
+        landings: vec![0.35, 1.4, 2.3, 3.3],
+        ..sweep.clone()
+    };
+    let rig = Rig::with_shafts(&fast_building(), vec![sweep.clone(), offset.clone()]);
+    let file = TempFile::new();
+
+    rig.set_position(Elevator(0), 1.7);
+    rig.set_position(Elevator(1), 0.9);
+
+    // Act
+    let mut control = start_fleet(&fast_building(), &rig, &file);
+    control.stop().unwrap();
+
+    let saved = calibration_control::read_calibration(&file.0, 4, 2).unwrap();
+
+    // Assert
+    for (elevator, shaft) in [(Elevator(0), sweep), (Elevator(1), offset)] {
+        let saved = saved[elevator.index()].as_ref().unwrap();
+        let found = &saved.positions;
+
+        assert_eq!(saved.elevator as usize, elevator.index() + 1);
+
+        for (position, landing) in found.iter().zip(shaft.landings) {
+            let real = (landing * shaft.ticks_per_metre).round() as i32;
+
+            assert!(
+                (position - real).abs() < 80,
+                "elevator {:?}: {} {}",
+                elevator,
+                position,
+                real
+            );
+        }
+
+        assert!(rig.log(elevator).is_stopped());
+    }
+}
+
+#[test]
+fn fleet_keeps_serving_during_a_sweep() {
+    // Arrange
+    let (rig, mut control, _file) = calibrated_fleet(&fast_building(), 0.2);
+    let sweeping = AtomicBool::new(true);
+
+    // Act
+    let (recalibrated, served) = thread::scope(|scope| {
+        let sweep = scope.spawn(|| {
+            let result = control.recalibrate(Elevator(0));
+            sweeping.store(false, Relaxed);
+            result
+        });
+
+        // Once the sweep drives the first car, the second one is called
+        wait_until(Duration::from_secs(5), || {
+            rig.log(Elevator(0)).direction() != Direction::Stop
+        });
+        rig.press(car_call(Elevator(1), 1));
+        rig.press(car_call(Elevator(0), 2));
+
+        let served = wait_until(Duration::from_secs(10), || {
+            !rig.is_lit(car_call(Elevator(1), 1))
+        });
+        let during_sweep = sweeping.load(Relaxed);
+
+        (sweep.join().unwrap(), served && during_sweep)
+    });
+
+    // The call pressed in the sweeping car is dropped with the ones pending before it
+    let dropped = !rig.is_lit(car_call(Elevator(0), 2));
+
+    control.stop().unwrap();
+
+    // Assert
+    assert!(recalibrated.is_ok());
+    assert!(served);
+    assert!(dropped);
+    assert!((rig.position(Elevator(1)) - landing(1)).abs() <= 300);
+}
+
+// Recalibrates the first car and interrupts the sweep once it drives the motor
+fn interrupt_sweep(
+    control: &mut ElevatorControl<MemoryTransport, MemoryDisplay>,
+    rig: &Rig,
+    interrupt: impl FnOnce(),
+) -> crate::error::Result<()> {
+    thread::scope(|scope| {
+        let sweep = scope.spawn(|| control.recalibrate(Elevator(0)));
+
+        assert!(wait_until(Duration::from_secs(5), || {
+            rig.log(Elevator(0)).direction() != Direction::Stop
+        }));
+        interrupt();
+
+        sweep.join().unwrap()
+    })
+}
+
+#[test]
+fn fleet_stops_an_aborted_sweep_and_returns_to_service() {
+    // Arrange
+    let (rig, mut control, file) = calibrated_fleet(&fast_building(), 1.2);
+    let saved = fs::read(&file.0).unwrap();
+    let abort = control.calibration_abort_flag();
+
+    // Act
+    let result = interrupt_sweep(&mut control, &rig, || abort.store(true, Relaxed));
+    let stopped = rig.log(Elevator(0)).is_stopped();
+
+    abort.store(false, Relaxed);
+    rig.press(car_call(Elevator(0), 2));
+
+    let served = wait_until(Duration::from_secs(10), || {
+        !rig.is_lit(car_call(Elevator(0), 2))
+    });
+    control.stop().unwrap();
+
+    // Assert
+    assert!(matches!(
+        result,
+        Err(Error::Calibration(CalibrationError::Aborted))
+    ));
+    assert!(stopped);
+    assert_eq!(fs::read(&file.0).unwrap(), saved);
+    assert!(served);
+    assert!((rig.position(Elevator(0)) - landing(2)).abs() <= 300);
+    assert!(rig.log(Elevator(0)).is_stopped());
+}
+
+#[test]
+fn fleet_stops_a_sweep_on_the_emergency_button() {
+    // Arrange
+    let (rig, mut control, file) = calibrated_fleet(&fast_building(), 1.2);
+    let saved = fs::read(&file.0).unwrap();
+
+    // Act
+    let result = interrupt_sweep(&mut control, &rig, || rig.press(emergency(Elevator(0))));
+    let stopped = rig.log(Elevator(0)).is_stopped();
+    let lit = rig.is_lit(emergency(Elevator(0)));
+
+    control.stop().unwrap();
+
+    // Assert
+    assert!(matches!(
+        result,
+        Err(Error::Calibration(CalibrationError::Aborted))
+    ));
+    assert!(stopped);
+    assert!(lit);
+    assert_eq!(fs::read(&file.0).unwrap(), saved);
+}
+
+#[test]
+fn fleet_keeps_the_calibration_of_a_sweep_out_of_bounds() {
+    // Arrange
+    let (rig, mut control, file) = calibrated_fleet(&fast_building(), 1.2);
+    let saved = fs::read(&file.0).unwrap();
+
+    // Landings are 2500 ticks apart, the sensor of the first floor is never reached
+    control.set_calibration_limits(CalibrationLimits {
+        floor_travel: 1000,
+        ..CalibrationLimits::default()
+    });
+
+    // Act
+    let result = control.recalibrate(Elevator(0));
+    let stopped = rig.log(Elevator(0)).is_stopped();
+
+    rig.press(car_call(Elevator(0), 2));
+
+    let served = wait_until(Duration::from_secs(10), || {
+        !rig.is_lit(car_call(Elevator(0), 2))
+    });
+    control.stop().unwrap();
+
+    // Assert
+    assert!(matches!(
+        result,
+        Err(Error::Calibration(CalibrationError::Sensor(Floor(1), _)))
+    ));
+    assert!(stopped);
+    assert_eq!(fs::read(&file.0).unwrap(), saved);
+    assert!(served);
+    assert!((rig.position(Elevator(0)) - landing(2)).abs() <= 300);
+}
+
+// Moves a car to the landing and answers the calls there, returning the direction it leaves in
+fn arrive(calls: &mut CallSet, from: Floor, to: Floor, direction: Direction) -> Direction {
+    let arriving = match to.cmp(&from) {
+        Ordering::Greater => Up,
+        Ordering::Less => Down,
+        Ordering::Equal => direction,
+    };
+
+    let leaving = calls.departure(to, arriving);
+    calls.serve(to, leaving);
+
+    leaving
+}
+
+#[test]
+fn calls_served_in_direction_of_travel() {
+    // Arrange
+    let mut calls = CallSet::new();
+    calls.insert(Call::Car(Floor(3)));
+    calls.insert(Call::Hall(Floor(1), Up));
+    calls.insert(Call::Car(Floor(2)));
+
+    // Act
+    let mut stops = Vec::new();
+    let (mut floor, mut direction) = (Floor(0), Stop);
+
+    while let Some(next) = calls.next(Some(floor), direction) {
+        direction = arrive(&mut calls, floor, next, direction);
+        floor = next;
+        stops.push(floor);
+
+        if floor == Floor(2) {
+            calls.insert(Call::Car(Floor(0)));
+            calls.insert(Call::Hall(Floor(1), Down));
+        }
+    }
+
+    // Assert
+    assert_eq!(stops, [Floor(1), Floor(2), Floor(3), Floor(1), Floor(0)]);
+    assert!(calls.is_empty());
+}
+
+#[test]
+fn calls_answered_nearest_first_when_idle() {
+    // Arrange
+    let mut calls = CallSet::new();
+    calls.insert(Call::Car(Floor(0)));
+    calls.insert(Call::Car(Floor(3)));
+
+    // Act
+    let idle = calls.next(Some(Floor(2)), Stop);
+    let going_down = calls.next(Some(Floor(2)), Down);
+    let unknown = calls.next(None, Up);
+    let repeated = calls.insert(Call::Car(Floor(0)));
+
+    // Assert
+    assert_eq!(idle, Some(Floor(3)));
+    assert_eq!(going_down, Some(Floor(0)));
+    assert_eq!(unknown, Some(Floor(0)));
+    assert!(!repeated);
+}
+
+#[test]
+fn calls_on_the_way_stop_the_car_when_reachable() {
+    // Arrange
+    let landings = Landings::from_calibration(&calibration(1));
+
+    let mut calls = CallSet::new();
+    calls.insert(Call::Car(Floor(3)));
+    calls.insert(Call::Car(Floor(2)));
+    calls.insert(Call::Hall(Floor(1), Up));
+
+    // Act: going up from the ground to the third floor
+    let leaving = calls.stop_before(Floor(3), Up, &landings, 2000);
+    let past_first = calls.stop_before(Floor(3), Up, &landings, 9000);
+    let too_close = calls.stop_before(Floor(3), Up, &landings, 15950);
+    let going_down = calls.stop_before(Floor(0), Down, &landings, 20000);
+
+    // Assert
+    assert_eq!(leaving, Some(Floor(1)));
+    assert_eq!(past_first, Some(Floor(2)));
+    assert_eq!(too_close, None);
+    assert_eq!(going_down, Some(Floor(2)));
+}
+
+// Calls the first car to the landing and waits until its cabin passes the given height (m)
+fn call_and_pass(rig: &Rig, floor: usize, height: f64) -> Button {
+    let button = car_call(Elevator(0), floor);
+    let ticks = (height * fast_plant().ticks_per_metre) as i32;
+
+    rig.press(button);
+    assert!(wait_until(Duration::from_secs(10), || {
+        rig.position(Elevator(0)) > ticks
+    }));
+
+    button
+}
+
+// Landings of the fast plant, as a perfect sweep would find them
+fn plant_landings() -> Landings {
+    Landings::new((0..4).map(band).collect())
+}
+
+fn is_level(rig: &Rig, elevator: Elevator, floor: Floor) -> bool {
+    plant_landings().locate(rig.position(elevator)) == CarPosition::AtFloor(floor)
+}
+
+#[test]
+fn fleet_stops_on_the_way_at_a_later_call() {
+    // Arrange
+    let (rig, mut control, _file) = calibrated_fleet(&fast_building(), 0.2);
+    let top = call_and_pass(&rig, 3, 0.5);
+    let on_the_way = car_call(Elevator(0), 2);
+
+    // Act
+    rig.press(on_the_way);
+
+    let stopped = wait_until(Duration::from_secs(10), || !rig.is_lit(on_the_way));
+    let stopped_at = is_level(&rig, Elevator(0), Floor(2));
+    let top_kept = rig.is_lit(top);
+
+    let served = wait_until(Duration::from_secs(15), || !rig.is_lit(top));
+    control.stop().unwrap();
+
+    // Assert
+    assert!(stopped);
+    assert!(stopped_at);
+    assert!(top_kept);
+    assert!(served);
+    assert!(is_level(&rig, Elevator(0), Floor(3)));
+    assert!(rig.log(Elevator(0)).is_stopped());
+}
+
+#[test]
+fn hall_calls_answered_in_their_direction() {
+    // Arrange
+    let landings = Landings::from_calibration(&calibration(1));
+
+    let mut calls = CallSet::new();
+    calls.insert(Call::Car(Floor(3)));
+    calls.insert(Call::Hall(Floor(2), Down));
+    calls.insert(Call::Hall(Floor(1), Up));
+    calls.insert(Call::Hall(Floor(1), Down));
+
+    // Act
+    let passing_down_call = calls.stop_before(Floor(3), Up, &landings, 9000);
+
+    let mut stops = Vec::new();
+    let mut answered = Vec::new();
+    let (mut floor, mut direction) = (Floor(0), Stop);
+
+    while let Some(next) = calls.next(Some(floor), direction) {
+        let pending = calls.clone();
+        direction = arrive(&mut calls, floor, next, direction);
+        floor = next;
+        stops.push(floor);
+        answered.push(pending.iter().count() - calls.iter().count());
+    }
+
+    // Assert
+    assert_eq!(passing_down_call, None);
+    assert_eq!(stops, [Floor(1), Floor(3), Floor(2), Floor(1)]);
+    assert_eq!(answered, [1, 1, 1, 1]);
+}
+
+#[test]
+fn dispatcher_assigns_the_car_arriving_first() {
+    // Arrange
+    let car = |floor: usize, direction: Direction, calls: &[Call]| {
+        let mut set = CallSet::new();
+
+        for call in calls {
+            set.insert(*call);
+        }
+
+        (
+            Arc::new(RwLock::new(set)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(RwLock::new(CarStatus {
+                floor: Some(Floor(floor)),
+                direction,
+                doors: DoorState::Closed,
+            })),
+        )
+    };
+
+    // Car 1 rises from the ground to the third floor, car 2 waits on the third floor
+    let rising = car(0, Up, &[Call::Car(Floor(3))]);
+    let waiting = car(3, Stop, &[]);
+
+    let mut dispatcher = Dispatcher::new();
+
+    for (calls, emergency, available, status) in [rising.clone(), waiting.clone()] {
+        dispatcher.add_car(calls, emergency, available, status);
+    }
+
+    // Act
+    let on_the_way = dispatcher.assign(Call::Hall(Floor(1), Up), None);
+    let going_down = dispatcher.assign(Call::Hall(Floor(2), Down), None);
+    let pending = dispatcher.is_pending(Call::Hall(Floor(2), Down));
+
+    waiting.1.store(true, Relaxed);
+    let in_emergency = dispatcher.assign(Call::Hall(Floor(2), Up), None);
+    let excluded = dispatcher.assign(Call::Hall(Floor(0), Up), Some(Elevator(0)));
+
+    waiting.1.store(false, Relaxed);
+    rising.2.store(false, Relaxed);
+    let unavailable = dispatcher.assign(Call::Hall(Floor(1), Down), None);
+
+    // Assert
+    assert_eq!(on_the_way, Some(Elevator(0)));
+    assert_eq!(going_down, Some(Elevator(1)));
+    assert!(pending);
+    assert_eq!(in_emergency, Some(Elevator(0)));
+    assert_eq!(excluded, None);
+    assert_eq!(unavailable, Some(Elevator(1)));
+    assert!(rising.0.read().unwrap().contains(Call::Hall(Floor(2), Up)));
+}
+
+#[test]
+fn time_to_arrive_counts_floors_and_stops() {
+    // Arrange
+    let status = CarStatus {
+        floor: Some(Floor(0)),
+        direction: Up,
+        doors: DoorState::Closed,
+    };
+
+    let mut calls = CallSet::new();
+    calls.insert(Call::Car(Floor(2)));
+
+    // Act
+    let on_the_way = time_to_arrive(status, &calls, Call::Hall(Floor(1), Up));
+    let after_stop = time_to_arrive(status, &calls, Call::Car(Floor(3)));
+    let coming_back = time_to_arrive(status, &calls, Call::Hall(Floor(1), Down));
+    let unknown = time_to_arrive(
+        CarStatus {
+            floor: None,
+            direction: Stop,
+            doors: DoorState::Closed,
+        },
+        &calls,
+        Call::Car(Floor(3)),
+    );
+
+    // Assert
+    // 3 s per floor and 4 s per stop on the way
+    assert_eq!(on_the_way, Some(Duration::from_secs(3)));
+    assert_eq!(after_stop, Some(Duration::from_secs(6 + 4 + 3)));
+    assert_eq!(coming_back, Some(Duration::from_secs(6 + 4 + 3)));
+    assert_eq!(unknown, None);
+}
+
+#[test]
+fn doors_cycle_through_the_dwell() {
+    // Arrange
+    let config = DoorConfig {
+        travel: Duration::from_secs(1),
+        dwell: Duration::from_secs(2),
+    };
+    let mut doors = Doors::new(config);
+    let start = Instant::now();
+    let at = |secs: f64| start + Duration::from_secs_f64(secs);
+
+    // Act
+    let opening = doors.open(at(0.0));
+    let still_opening = doors.update(at(0.5));
+    let open = doors.update(at(1.0));
+    let dwelling = doors.update(at(2.5));
+    let closing = doors.update(at(3.0));
+    let closed = doors.update(at(4.0));
+
+    // Assert
+    assert_eq!(opening, Some(DoorState::Opening));
+    assert_eq!(still_opening, None);
+    assert_eq!(open, Some(DoorState::Open));
+    assert_eq!(dwelling, None);
+    assert_eq!(closing, Some(DoorState::Closing));
+    assert_eq!(closed, Some(DoorState::Closed));
+    assert!(doors.is_closed());
+}
+
+#[test]
+fn door_commands_cut_the_dwell_and_reopen() {
+    // Arrange
+    let mut doors = Doors::new(DoorConfig::default());
+    let start = Instant::now();
+    let at = |millis: u64| start + Duration::from_millis(millis);
+
+    doors.open(at(0));
+    doors.update(at(1000));
+
+    // Act
+    let closing = doors.command(DoorCommand::Close, at(1200));
+    let reopening = doors.command(DoorCommand::Open, at(1500));
+    let ignored = doors.command(DoorCommand::Close, at(1600));
+    // Only the 300 ms already closed are opened again
+    let early = doors.update(at(1700));
+    let open = doors.update(at(1800));
+
+    // Assert
+    assert_eq!(closing, Some(DoorState::Closing));
+    assert_eq!(reopening, Some(DoorState::Reopening));
+    assert_eq!(ignored, None);
+    assert_eq!(early, None);
+    assert_eq!(open, Some(DoorState::Open));
+    assert_eq!(doors.state(), DoorState::Open);
+}
+
+#[test]
+fn fleet_relevels_after_an_emergency_reset() {
+    // Arrange
+    let (rig, mut control, _file) = calibrated_fleet(&fast_building(), 0.2);
+    let call = call_and_pass(&rig, 3, 1.35);
+
+    // Act
+    rig.press(emergency(Elevator(0)));
+    let stopped = wait_until(Duration::from_secs(5), || {
+        control.in_emergency() == [Elevator(0)] && rig.log(Elevator(0)).is_stopped()
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    let stranded = plant_landings().locate(rig.position(Elevator(0)));
+    let nearest = plant_landings().nearest(rig.position(Elevator(0))).unwrap();
+    let emergency_lit = rig.is_lit(emergency(Elevator(0)));
+    let call_lit = rig.is_lit(call);
+
+    control.reset_emergency(Elevator(0)).unwrap();
+    let recovered = wait_until(Duration::from_secs(15), || {
+        control.in_emergency().is_empty()
+            && is_level(&rig, Elevator(0), nearest)
+            && rig.log(Elevator(0)).is_stopped()
+    });
+    control.stop().unwrap();
+
+    // Assert
+    assert!(stopped);
+    assert!(emergency_lit);
+    assert!(!call_lit);
+    assert!(matches!(stranded, CarPosition::BetweenFloors { .. }));
+    assert!(recovered);
+    assert!(!rig.is_lit(emergency(Elevator(0))));
+    assert!(!rig.is_lit(call));
+    assert!(rig.log(Elevator(0)).is_stopped());
+}
+
+#[test]
+fn soft_emergency_levels_at_the_next_reachable_landing() {
+    // Arrange
+    let landings = Landings::from_calibration(&calibration(1));
+
+    // Act
+    let going_up = emergency_stop::landing_ahead(&landings, Up, 8000);
+    let too_close = emergency_stop::landing_ahead(&landings, Up, 8500);
+    let going_down = emergency_stop::landing_ahead(&landings, Down, 16000);
+    let past_the_top = emergency_stop::landing_ahead(&landings, Up, 23700);
+
+    // Assert
+    assert_eq!(going_up, Some(Floor(1)));
+    assert_eq!(too_close, Some(Floor(2)));
+    assert_eq!(going_down, Some(Floor(1)));
+    assert_eq!(past_the_top, None);
+}
+
+#[test]
+fn soft_emergency_sheds_the_duty_cycle_at_its_rate() {
+    // Arrange
+    let config = EmergencyConfig {
+        profile: StopProfile::Soft,
+        deceleration: 0.5,
+    };
+    let start = Instant::now();
+    let mut braking = EmergencyStop::new(config, 0.8, 10_000, start);
+
+    // Act
+    let after_200ms = braking.limit(start + Duration::from_millis(200));
+    let after_1s = braking.limit(start + Duration::from_secs(1));
+    let after_3s = braking.limit(start + Duration::from_secs(3));
+    let (distance, time) = braking.measure(9_400, start + Duration::from_secs(3));
+
+    // Assert
+    assert!((after_200ms - 0.7).abs() < 1e-9);
+    assert!((after_1s - 0.3).abs() < 1e-9);
+    assert_eq!(after_3s, 0.0);
+    assert_eq!(distance, 600);
+    assert_eq!(time, Duration::from_secs(3));
+}
+
+// Stops the first car with its emergency button just past the second landing, the panel is read every 500 ms
+fn emergency_stop_on_the_plant(profile: StopProfile) -> (Rig, CarPosition) {
+    let mut building = fast_building();
+
+    for car in &mut building.cars {
+        car.emergency_stop.profile = profile;
+    }
+
+    let (rig, mut control, _file) = calibrated_fleet(&building, 0.2);
+    let call = call_and_pass(&rig, 3, 1.35);
+
+    rig.press(emergency(Elevator(0)));
+
+    let stopped = wait_until(Duration::from_secs(10), || {
+        control.in_emergency() == [Elevator(0)] && rig.log(Elevator(0)).is_stopped()
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    // Stopping the fleet turns off every lamp
+    assert!(stopped);
+    assert!(!rig.is_lit(call));
+    assert!(rig.is_lit(emergency(Elevator(0))));
+
+    control.stop().unwrap();
+
+    let stopped_at = plant_landings().locate(rig.position(Elevator(0)));
+
+    (rig, stopped_at)
+}
+
+#[test]
+fn hard_emergency_stop_halts_between_floors() {
+    // Act
+    let (rig, stopped_at) = emergency_stop_on_the_plant(StopProfile::Hard);
+
+    // Assert
+    assert!(matches!(stopped_at, CarPosition::BetweenFloors { .. }));
+    assert!(rig.log(Elevator(0)).is_stopped());
+}
+
+#[test]
+fn soft_emergency_stop_levels_at_the_landing_ahead() {
+    // Act
+    let (rig, stopped_at) = emergency_stop_on_the_plant(StopProfile::Soft);
+
+    // Assert
+    assert!(
+        matches!(stopped_at, CarPosition::AtFloor(floor) if floor > Floor(1)),
+        "{:?}",
+        stopped_at
+    );
+    assert!(rig.log(Elevator(0)).is_stopped());
+}
+
+// References every 10 ms over the trip and a second past its end
+fn sampled(trajectory: &Trajectory) -> Vec<Reference> {
+    let steps = (trajectory.duration().as_secs_f64() * 100.0) as u64 + 100;
+
+    (0..=steps)
+        .map(|step| trajectory.at(Duration::from_millis(step * 10)))
+        .collect()
+}
+
+#[test]
+fn trajectory_keeps_speed_and_acceleration_limits() {
+    // Arrange
+    let limits = TrajectoryLimits::default();
+
+    // Act
+    let long = Trajectory::new(1000, 23500, &limits);
+    let down = Trajectory::new(8500, 1000, &limits);
+    let references = sampled(&long);
+
+    // Assert
+    // Ramps of 2 s each reach 3000 ticks/s, the cruise covers what is left
+    assert!((long.duration().as_secs_f64() - (4.0 + 16500.0 / 3000.0)).abs() < 1e-6);
+    assert!((down.duration().as_secs_f64() - 4.5).abs() < 1e-6);
+    assert_eq!(long.at(Duration::ZERO).position, 1000.0);
+    assert!((references.last().unwrap().position - 23500.0).abs() < 1e-6);
+    assert!((down.at(down.duration()).position - 1000.0).abs() < 1e-6);
+    assert!(down.at(Duration::from_secs(2)).velocity < 0.0);
+
+    for pair in references.windows(2) {
+        let acceleration = (pair[1].velocity - pair[0].velocity) / 0.01;
+
+        assert!(pair[1].velocity <= limits.speed + 1e-6);
+        assert!(pair[1].position >= pair[0].position);
+        assert!(acceleration.abs() <= limits.acceleration + 1e-6);
+    }
+}
+
+#[test]
+fn trajectory_of_a_short_trip_cruises_slower() {
+    // Arrange
+    let limits = TrajectoryLimits::default();
+
+    // Act
+    let short = Trajectory::new(0, 1000, &limits);
+    let top_speed = sampled(&short)
+        .iter()
+        .map(|reference| reference.velocity)
+        .fold(0.0, f64::max);
+
+    // Assert
+    assert!(top_speed < limits.speed);
+    assert!((short.at(short.duration()).position - 1000.0).abs() < 1e-6);
+}
+
+#[test]
+fn trajectory_replanned_on_the_way_continues_smoothly() {
+    // Arrange
+    let limits = TrajectoryLimits::default();
+    let first = Trajectory::new(1000, 23500, &limits);
+    let from = first.at(Duration::from_secs(3));
+
+    // Act
+    let closer = Trajectory::from_motion(from, 16000, &limits);
+    let start = closer.at(Duration::ZERO);
+    let end = closer.at(closer.duration());
+
+    // Assert
+    assert!((start.position - from.position).abs() < 1e-6);
+    assert!((start.velocity - from.velocity).abs() < 1e-6);
+    assert!((end.position - 16000.0).abs() < 1e-6);
+    assert_eq!(end.velocity, 0.0);
+}
+
+#[test]
+fn fleet_follows_the_s_curve_of_a_trip() {
+    // Arrange
+    let (rig, mut control, _file) = calibrated_fleet(&fast_building(), 0.2);
+    let button = car_call(Elevator(0), 2);
+    let from = Reference {
+        position: rig.position(Elevator(0)) as f64,
+        velocity: 0.0,
+    };
+    let planned = Trajectory::from_motion(from, band(2).center(), &fast_trajectory());
+    let top_speed = sampled(&planned)
+        .iter()
+        .map(|reference| reference.velocity)
+        .fold(0.0, f64::max);
+
+    // Act
+    rig.press(button);
+    let served = wait_until(Duration::from_secs(15), || !rig.is_lit(button));
+    control.stop().unwrap();
+
+    // Assert
+    let potencies: Vec<f64> = rig
+        .log(Elevator(0))
+        .commands()
+        .into_iter()
+        .filter_map(|(_, command)| match command {
+            MotorCommand::Potency(potency) => Some(potency),
+            _ => None,
+        })
+        .collect();
+    let cruise = top_speed / fast_trajectory().full_speed;
+    let peak = potencies.iter().copied().fold(0.0, f64::max);
+
+    assert!(served);
+    assert!(is_level(&rig, Elevator(0), Floor(2)));
+    assert!(rig.log(Elevator(0)).is_stopped());
+
+    // A smooth start from rest, cruising at the planned speed instead of saturating the motor
+    assert!(potencies.iter().find(|potency| **potency > 0.0).unwrap() < &0.1);
+    assert!(
+        (peak - cruise).abs() < 0.15,
+        "{} for {} planned",
+        peak,
+        cruise
+    );
+}
+
+// Highest duty cycle the motor of the car was commanded
+fn peak_potency(log: &MotorLog) -> f64 {
+    log.commands()
+        .into_iter()
+        .filter_map(|(_, command)| match command {
+            MotorCommand::Potency(potency) => Some(potency),
+            _ => None,
+        })
+        .fold(0.0, f64::max)
+}
+
+#[test]
+fn fleet_corrects_a_weak_feedforward_with_the_gains_of_each_car() {
+    // Arrange
+    let mut building = fast_building();
+
+    // Both cars believe the motor twice as fast as it is, only the controller makes up the difference
+    for car in &mut building.cars {
+        car.trajectory.full_speed *= 2.0;
+    }
+
+    building.cars[1].pid = PidGains {
+        kp: 0.0005,
+        ki: 0.0005,
+        ..PidGains::default()
+    };
+
+    let (rig, mut control, _file) = calibrated_fleet(&building, 0.2);
+    let buttons: Vec<_> = building
+        .elevators()
+        .map(|elevator| car_call(elevator, 3))
+        .collect();
+
+    // Act
+    for button in &buttons {
+        rig.press(*button);
+    }
+
+    let served = wait_until(Duration::from_secs(30), || {
+        buttons.iter().all(|button| !rig.is_lit(*button))
+    });
+    control.stop().unwrap();
+
+    // Assert
+    assert!(served);
+
+    for elevator in building.elevators() {
+        assert!(is_level(&rig, elevator, Floor(3)));
+        assert!(rig.log(elevator).is_stopped());
+    }
+
+    // The stiffer gains push the motor past the halved feedforward the default ones barely correct
+    let cruise = fast_trajectory().speed / (2.0 * fast_trajectory().full_speed);
+    let default = peak_potency(&rig.log(Elevator(0)));
+    let tuned = peak_potency(&rig.log(Elevator(1)));
+
+    assert!(tuned > cruise + 0.1, "{} for {} fed forward", tuned, cruise);
+    assert!(tuned > default, "{} against {}", tuned, default);
+}
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Steps of the search for the cruise speed of a trip too short to reach the configured one
+const SEARCH_STEPS: u32 = 40;
+
+/// Bounds of the reference followed by the car, in encoder ticks and seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrajectoryLimits {
+    /// Speed of the car at full duty cycle, feeds the reference speed forward to the motor
+    pub full_speed: f64,
+    /// Cruise speed, below the full one to leave the controller room to correct
+    pub speed: f64,
+    pub acceleration: f64,
+    pub jerk: f64,
+}
+
+impl Default for TrajectoryLimits {
+    fn default() -> Self {
+        TrajectoryLimits {
+            full_speed: 5000.0,
+            speed: 3000.0,
+            acceleration: 2000.0,
+            jerk: 4000.0,
+        }
+    }
+}
+
+/// Point of the reference at some time of the trip.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reference {
+    pub position: f64,
+    /// Signed, positive going up
+    pub velocity: f64,
+}
+
+/// Jerk-limited change of speed from rest, the building block of the S-curve.
+#[derive(Clone, Copy, Debug)]
+struct Ramp {
+    /// Time spent at the jerk limit on each end
+    jerk_time: f64,
+    /// Time spent at the peak acceleration in the middle
+    constant_time: f64,
+    peak: f64,
+    jerk: f64,
+}
+
+impl Ramp {
+    fn new(speed_change: f64, limits: &TrajectoryLimits) -> Self {
+        let jerk = limits.jerk;
+
+        // Too small a change reaches no constant acceleration
+        let (jerk_time, constant_time) = if speed_change * jerk < limits.acceleration.powi(2) {
+            ((speed_change / jerk).sqrt(), 0.0)
+        } else {
+            let jerk_time = limits.acceleration / jerk;
+            (jerk_time, speed_change / limits.acceleration - jerk_time)
+        };
+
+        Ramp {
+            jerk_time,
+            constant_time,
+            peak: jerk * jerk_time,
+            jerk,
+        }
+    }
+
+    fn duration(&self) -> f64 {
+        2.0 * self.jerk_time + self.constant_time
+    }
+
+    /// Distance and speed gained `time` seconds into the ramp
+    fn at(&self, time: f64) -> (f64, f64) {
+        let (jerk, peak) = (self.jerk, self.peak);
+
+        let t1 = self.jerk_time;
+        let v1 = jerk * t1.powi(2) / 2.0;
+        let p1 = jerk * t1.powi(3) / 6.0;
+
+        let t2 = self.constant_time;
+        let v2 = v1 + peak * t2;
+        let p2 = p1 + v1 * t2 + peak * t2.powi(2) / 2.0;
+
+        let time = time.clamp(0.0, self.duration());
+
+        if time < t1 {
+            (jerk * time.powi(3) / 6.0, jerk * time.powi(2) / 2.0)
+        } else if time < t1 + t2 {
+            let s = time - t1;
+            (p1 + v1 * s + peak * s.powi(2) / 2.0, v1 + peak * s)
+        } else {
+            let s = time - t1 - t2;
+            (
+                p2 + v2 * s + peak * s.powi(2) / 2.0 - jerk * s.powi(3) / 6.0,
+                v2 + peak * s - jerk * s.powi(2) / 2.0,
+            )
+        }
+    }
+}
+
+/// S-curve reference of one trip: ramps to the cruise speed, cruises and ramps down to rest at the target.
+///
+/// Trips too short to reach the cruise speed turn around a lower one, keeping every limit.
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    start: f64,
+    /// 1.0 going up, -1.0 going down
+    sign: f64,
+    distance: f64,
+    initial_speed: f64,
+    cruise_speed: f64,
+    speed_up: Ramp,
+    /// Whether the first ramp slows the car down, when retargeted to a closer landing
+    slowing: bool,
+    cruise_time: f64,
+    slow_down: Ramp,
+}
+
+impl Trajectory {
+    /// Plans a trip from a car at rest
+    pub fn new(start: i32, target: i32, limits: &TrajectoryLimits) -> Self {
+        Trajectory::from_motion(
+            Reference {
+                position: start as f64,
+                velocity: 0.0,
+            },
+            target,
+            limits,
+        )
+    }
+
+    /// Plans a trip from a point of a previous reference, e.g. when the car is retargeted on the way
+    pub fn from_motion(from: Reference, target: i32, limits: &TrajectoryLimits) -> Self {
+        let offset = target as f64 - from.position;
+        let sign = if offset < 0.0 { -1.0 } else { 1.0 };
+        let distance = offset.abs();
+
+        // Speed along the trip, a car moving away from the target is brought back by the controller
+        let initial_speed = (from.velocity * sign).max(0.0);
+
+        let planned = |cruise_speed: f64| {
+            let speed_up = Ramp::new((cruise_speed - initial_speed).abs(), limits);
+            let slow_down = Ramp::new(cruise_speed, limits);
+            let ramps = (initial_speed + cruise_speed) / 2.0 * speed_up.duration()
+                + cruise_speed / 2.0 * slow_down.duration();
+
+            (speed_up, slow_down, ramps)
+        };
+
+        // Highest cruise speed whose ramps fit in the distance
+        let (mut low, mut high) = (0.0, limits.speed.max(initial_speed));
+
+        if planned(high).2 > distance {
+            for _ in 0..SEARCH_STEPS {
+                let middle = (low + high) / 2.0;
+
+                if planned(middle).2 > distance {
+                    high = middle;
+                } else {
+                    low = middle;
+                }
+            }
+
+            high = low;
+        }
+
+        let cruise_speed = high;
+        let (speed_up, slow_down, ramps) = planned(cruise_speed);
+
+        Trajectory {
+            start: from.position,
+            sign,
+            distance,
+            initial_speed,
+            cruise_speed,
+            speed_up,
+            slowing: cruise_speed < initial_speed,
+            cruise_time: ((distance - ramps) / cruise_speed.max(f64::EPSILON)).max(0.0),
+            slow_down,
+        }
+    }
+
+    /// Time the reference takes to reach the target
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(
+            self.speed_up.duration() + self.cruise_time + self.slow_down.duration(),
+        )
+    }
+
+    /// Reference `elapsed` into the trip, at rest on the target once it is over
+    pub fn at(&self, elapsed: Duration) -> Reference {
+        let time = elapsed.as_secs_f64();
+
+        let speed_up = self.speed_up.duration();
+        let cruise_end = speed_up + self.cruise_time;
+        let end = cruise_end + self.slow_down.duration();
+
+        let (distance, speed) = if time < speed_up {
+            let (gained, change) = self.speed_up.at(time);
+            let direction = if self.slowing { -1.0 } else { 1.0 };
+
+            (
+                self.initial_speed * time + direction * gained,
+                self.initial_speed + direction * change,
+            )
+        } else if time < cruise_end {
+            let (gained, _) = self.speed_up.at(speed_up);
+            let direction = if self.slowing { -1.0 } else { 1.0 };
+            let ramped = self.initial_speed * speed_up + direction * gained;
+
+            (
+                ramped + self.cruise_speed * (time - speed_up),
+                self.cruise_speed,
+            )
+        } else if time < end {
+            // The slow down mirrors a ramp from rest ending on the target
+            let (lost, change) = self.slow_down.at(end - time);
+
+            (self.distance - lost, change)
+        } else {
+            (self.distance, 0.0)
+        };
+
+        Reference {
+            position: self.start + self.sign * distance,
+            velocity: self.sign * speed,
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::common::Floor;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Transport(TransportError),
+    Protocol(ProtocolError),
+    Device(DeviceError),
+    Calibration(CalibrationError),
+    /// The building topology is inconsistent or could not be read
+    Building(String),
+    /// The car cannot be moved in its current state, e.g. in emergency, out of service or with its doors open
+    Interlock(String),
+}
+
+/// Failures moving bytes over the serial link.
+#[derive(Debug)]
+pub enum TransportError {
+    Uart(rppal::uart::Error),
+    Io(io::Error),
+    ShortWrite { expected: usize, wrote: usize },
+    ShortRead { expected: usize, read: usize },
+}
+
+/// Bytes arrived but they are not what the modbus dialect expects.
+#[derive(Debug)]
+pub enum ProtocolError {
+    Length { minimum: usize, actual: usize },
+    Address { expected: u8, actual: u8 },
+    Code { expected: u8, actual: u8 },
+    Subcode { expected: u8, actual: u8 },
+    DataLength { expected: usize, actual: usize },
+    Crc { expected: u16, actual: u16 },
+    ButtonRange { start: u8, end: u8 },
+    StateLength { expected: usize, actual: usize },
+}
+
+/// A peripheral could not be opened or used.
+#[derive(Debug)]
+pub enum DeviceError {
+    Gpio(rppal::gpio::Error),
+    I2c(rppal::i2c::Error),
+    Uart(rppal::uart::Error),
+    Bme280(String),
+    Display(String),
+    Temperature(String),
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    NotFound,
+    File(io::Error),
+    Invalid(String),
+    /// The cabin did not reach the bottom of the shaft within the limits
+    Homing(String),
+    /// The sensor of the floor did not switch within the limits, probably disconnected
+    Sensor(Floor, String),
+    /// The floor has no landing in the calibration of the car
+    Uncalibrated(Floor),
+    Aborted,
+}
+
+impl Error {
+    /// Whether the serial link to the ESP32 failed, which may recover by itself
+    pub fn is_link(&self) -> bool {
+        matches!(self, Error::Transport(_) | Error::Protocol(_))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Protocol(e) => write!(f, "protocol error: {}", e),
+            Error::Device(e) => write!(f, "device error: {}", e),
+            Error::Calibration(e) => write!(f, "calibration error: {}", e),
+            Error::Building(e) => write!(f, "building error: {}", e),
+            Error::Interlock(e) => write!(f, "interlock: {}", e),
+        }
+    }
+}
+
+impl Display for TransportError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TransportError::Uart(e) => write!(f, "{}", e),
+            TransportError::Io(e) => write!(f, "{}", e),
+            TransportError::ShortWrite { expected, wrote } => {
+                write!(f, "wrote {} of {} bytes", wrote, expected)
+            }
+            TransportError::ShortRead { expected, read } => {
+                write!(f, "read {} of {} bytes", read, expected)
+            }
+        }
+    }
+}
+
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Length { minimum, actual } => {
+                write!(f, "invalid length: {} < {}", actual, minimum)
+            }
+            ProtocolError::Address { expected, actual } => {
+                write!(f, "invalid address: {:X} != {:X}", actual, expected)
+            }
+            ProtocolError::Code { expected, actual } => {
+                write!(f, "invalid code: {:X} != {:X}", actual, expected)
+            }
+            ProtocolError::Subcode { expected, actual } => {
+                write!(f, "invalid subcode: {:X} != {:X}", actual, expected)
+            }
+            ProtocolError::DataLength { expected, actual } => {
+                write!(f, "invalid data length: {} != {}", actual, expected)
+            }
+            ProtocolError::Crc { expected, actual } => {
+                write!(f, "invalid CRC16: {:X} != {:X}", actual, expected)
+            }
+            ProtocolError::ButtonRange { start, end } => {
+                write!(f, "invalid button range: {:X} - {:X}", start, end)
+            }
+            ProtocolError::StateLength { expected, actual } => {
+                write!(f, "invalid state length: {} != {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl Display for DeviceError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DeviceError::Gpio(e) => write!(f, "gpio: {}", e),
+            DeviceError::I2c(e) => write!(f, "i2c: {}", e),
+            DeviceError::Uart(e) => write!(f, "uart: {}", e),
+            DeviceError::Bme280(e) => write!(f, "bme280: {}", e),
+            DeviceError::Display(e) => write!(f, "ssd1306: {}", e),
+            DeviceError::Temperature(e) => write!(f, "temperature sensor: {}", e),
+        }
+    }
+}
+
+impl Display for CalibrationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CalibrationError::NotFound => write!(f, "calibration file not found"),
+            CalibrationError::File(e) => write!(f, "calibration file: {}", e),
+            CalibrationError::Invalid(e) => write!(f, "invalid calibration: {}", e),
+            CalibrationError::Homing(e) => write!(f, "homing failed: {}", e),
+            CalibrationError::Sensor(floor, e) => {
+                write!(f, "sensor of landing {} failed: {}", floor.index(), e)
+            }
+            CalibrationError::Uncalibrated(floor) => {
+                write!(f, "landing {} is not calibrated", floor.index())
+            }
+            CalibrationError::Aborted => write!(f, "calibration aborted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+impl std::error::Error for TransportError {}
+impl std::error::Error for ProtocolError {}
+impl std::error::Error for DeviceError {}
+impl std::error::Error for CalibrationError {}
+
+impl From<TransportError> for Error {
+    fn from(e: TransportError) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<ProtocolError> for Error {
+    fn from(e: ProtocolError) -> Self {
+        Error::Protocol(e)
+    }
+}
+
+impl From<DeviceError> for Error {
+    fn from(e: DeviceError) -> Self {
+        Error::Device(e)
+    }
+}
+
+impl From<CalibrationError> for Error {
+    fn from(e: CalibrationError) -> Self {
+        Error::Calibration(e)
+    }
+}
+
+impl From<rppal::uart::Error> for TransportError {
+    fn from(e: rppal::uart::Error) -> Self {
+        TransportError::Uart(e)
+    }
+}
+
+impl From<rppal::gpio::Error> for Error {
+    fn from(e: rppal::gpio::Error) -> Self {
+        Error::Device(DeviceError::Gpio(e))
+    }
+}
+
+impl From<rppal::i2c::Error> for Error {
+    fn from(e: rppal::i2c::Error) -> Self {
+        Error::Device(DeviceError::I2c(e))
+    }
+}
+
+impl From<io::Error> for CalibrationError {
+    fn from(e: io::Error) -> Self {
+        CalibrationError::File(e)
+    }
+}
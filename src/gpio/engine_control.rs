@@ -1,4 +1,6 @@
-use crate::common::{Direction, Elevator};
+use crate::common::Direction;
+use crate::error::Result;
+use crate::gpio::motor_driver::MotorDriver;
 use rppal::gpio::{Gpio, OutputPin};
 
 pub struct EngineControl {
@@ -8,30 +10,22 @@ pub struct EngineControl {
 }
 
 impl EngineControl {
-    pub fn new(elevator: Elevator) -> Self {
-        let gpio = Gpio::new().unwrap();
-
-        let instance = match elevator {
-            Elevator::One => EngineControl {
-                dir1_pin: gpio.get(20).unwrap().into_output_high(),
-                dir2_pin: gpio.get(21).unwrap().into_output_high(),
-                potm_pin: gpio.get(12).unwrap().into_output_low(),
-            },
-            Elevator::Two => EngineControl {
-                dir1_pin: gpio.get(19).unwrap().into_output_high(),
-                dir2_pin: gpio.get(26).unwrap().into_output_high(),
-                potm_pin: gpio.get(13).unwrap().into_output_low(),
-            },
-        };
-
-        instance
+    /// H-bridge wired to the given BCM pins: two direction inputs and the PWM enable.
+    pub fn with_pins(gpio: &Gpio, dir1: u8, dir2: u8, potm: u8) -> Result<Self> {
+        Ok(EngineControl {
+            dir1_pin: gpio.get(dir1)?.into_output_high(),
+            dir2_pin: gpio.get(dir2)?.into_output_high(),
+            potm_pin: gpio.get(potm)?.into_output_low(),
+        })
     }
+}
 
-    pub fn set_potency(&mut self, duty_cycle: f64) {
-        self.potm_pin.set_pwm_frequency(1000.0, duty_cycle).unwrap();
+impl MotorDriver for EngineControl {
+    fn set_potency(&mut self, duty_cycle: f64) -> Result<()> {
+        Ok(self.potm_pin.set_pwm_frequency(1000.0, duty_cycle)?)
     }
 
-    pub fn set_direction(&mut self, direction: Direction) {
+    fn set_direction(&mut self, direction: Direction) -> Result<()> {
         match direction {
             Direction::Up => {
                 self.dir1_pin.set_high();
@@ -46,5 +40,7 @@ impl EngineControl {
                 self.dir2_pin.set_high();
             }
         }
+
+        Ok(())
     }
 }
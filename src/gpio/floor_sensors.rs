@@ -0,0 +1,88 @@
+use crate::common::Floor;
+use crate::error::Result;
+use rppal::gpio::{Gpio, InputPin};
+use std::sync::{Arc, Mutex};
+
+/// Floor sensors of one shaft, one per landing from the ground up.
+pub trait FloorSensors: Send {
+    /// Number of landings with a sensor
+    fn landings(&self) -> usize;
+
+    /// Whether the sensor of the landing is active (cabin inside its band)
+    fn is_active(&self, landing: usize) -> bool;
+
+    fn is_at(&self, floor: Floor) -> bool {
+        floor.index() < self.landings() && self.is_active(floor.index())
+    }
+}
+
+/// Sensors wired to Raspberry Pi inputs, active high.
+pub struct GpioFloorSensors {
+    pins: Vec<InputPin>,
+}
+
+impl GpioFloorSensors {
+    pub fn new(gpio: &Gpio, pins: &[u8]) -> Result<Self> {
+        let pins = pins
+            .iter()
+            .map(|pin| Ok(gpio.get(*pin)?.into_input_pulldown()))
+            .collect::<Result<_>>()?;
+
+        Ok(GpioFloorSensors { pins })
+    }
+}
+
+impl FloorSensors for GpioFloorSensors {
+    fn landings(&self) -> usize {
+        self.pins.len()
+    }
+
+    fn is_active(&self, landing: usize) -> bool {
+        self.pins[landing].is_high()
+    }
+}
+
+/// Levels read by [`ScriptedFloorSensors`], kept by tests and simulators to inject edges.
+#[derive(Clone)]
+pub struct SensorLevels {
+    levels: Arc<Mutex<Vec<bool>>>,
+}
+
+impl SensorLevels {
+    pub fn set(&self, landing: usize, active: bool) {
+        self.levels.lock().unwrap()[landing] = active;
+    }
+
+    pub fn set_all(&self, levels: &[bool]) {
+        self.levels.lock().unwrap().copy_from_slice(levels);
+    }
+}
+
+/// Sensors whose levels are set by software.
+pub struct ScriptedFloorSensors {
+    levels: SensorLevels,
+}
+
+impl ScriptedFloorSensors {
+    pub fn new(landings: usize) -> Self {
+        ScriptedFloorSensors {
+            levels: SensorLevels {
+                levels: Arc::new(Mutex::new(vec![false; landings])),
+            },
+        }
+    }
+
+    pub fn levels(&self) -> SensorLevels {
+        self.levels.clone()
+    }
+}
+
+impl FloorSensors for ScriptedFloorSensors {
+    fn landings(&self) -> usize {
+        self.levels.levels.lock().unwrap().len()
+    }
+
+    fn is_active(&self, landing: usize) -> bool {
+        self.levels.levels.lock().unwrap()[landing]
+    }
+}
@@ -1,4 +1,7 @@
 pub mod engine_control;
+pub mod floor_sensors;
+pub mod motor_driver;
+pub mod motor_safety;
 pub mod pid;
 
 #[cfg(test)]
@@ -0,0 +1,153 @@
+use crate::common::Direction;
+use crate::error::Result;
+use std::sync::{Arc, Mutex, PoisonError, TryLockError};
+use std::time::Instant;
+
+/// Anything able to drive an elevator motor: the H-bridge on the Raspberry Pi, a mock, a simulator...
+pub trait MotorDriver: Send {
+    fn set_direction(&mut self, direction: Direction) -> Result<()>;
+
+    /// Duty cycle from 0.0 (stopped) to 1.0 (full power)
+    fn set_potency(&mut self, duty_cycle: f64) -> Result<()>;
+
+    /// Brakes and cuts the power, attempting both even if the first fails
+    fn stop(&mut self) -> Result<()> {
+        let direction = self.set_direction(Direction::Stop);
+        let potency = self.set_potency(0.0);
+
+        direction.and(potency)
+    }
+}
+
+/// Motor shared between its car and the safety shutdown, still usable after a thread panicked holding it.
+#[derive(Clone)]
+pub struct SharedMotor {
+    motor: Arc<Mutex<Box<dyn MotorDriver>>>,
+}
+
+impl SharedMotor {
+    pub fn new(motor: impl MotorDriver + 'static) -> Self {
+        SharedMotor {
+            motor: Arc::new(Mutex::new(Box::new(motor))),
+        }
+    }
+
+    /// Stops the motor without waiting, None when another thread is using it right now
+    pub fn try_stop(&self) -> Option<Result<()>> {
+        match self.motor.try_lock() {
+            Ok(mut motor) => Some(motor.stop()),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner().stop()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+}
+
+impl MotorDriver for SharedMotor {
+    fn set_direction(&mut self, direction: Direction) -> Result<()> {
+        self.motor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_direction(direction)
+    }
+
+    fn set_potency(&mut self, duty_cycle: f64) -> Result<()> {
+        self.motor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_potency(duty_cycle)
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.motor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .stop()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MotorCommand {
+    Direction(Direction),
+    Potency(f64),
+}
+
+/// Commands received by a [`RecordingMotor`], shared so it can be inspected after the motor is moved away.
+#[derive(Clone, Default)]
+pub struct MotorLog {
+    commands: Arc<Mutex<Vec<(Instant, MotorCommand)>>>,
+}
+
+impl MotorLog {
+    pub fn commands(&self) -> Vec<(Instant, MotorCommand)> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.commands
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find_map(|(_, command)| match command {
+                MotorCommand::Direction(direction) => Some(*direction),
+                _ => None,
+            })
+            .unwrap_or(Direction::Stop)
+    }
+
+    pub fn potency(&self) -> f64 {
+        self.commands
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find_map(|(_, command)| match command {
+                MotorCommand::Potency(potency) => Some(*potency),
+                _ => None,
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Whether the last commands left the motor stopped with no power
+    pub fn is_stopped(&self) -> bool {
+        self.direction() == Direction::Stop && self.potency() == 0.0
+    }
+}
+
+/// Mock motor that timestamps every command it receives, its clones share the log.
+#[derive(Clone, Default)]
+pub struct RecordingMotor {
+    log: MotorLog,
+}
+
+impl RecordingMotor {
+    pub fn new() -> Self {
+        RecordingMotor::default()
+    }
+
+    pub fn log(&self) -> MotorLog {
+        self.log.clone()
+    }
+
+    fn record(&mut self, command: MotorCommand) {
+        self.log
+            .commands
+            .lock()
+            .unwrap()
+            .push((Instant::now(), command));
+    }
+}
+
+impl MotorDriver for RecordingMotor {
+    fn set_direction(&mut self, direction: Direction) -> Result<()> {
+        self.record(MotorCommand::Direction(direction));
+
+        Ok(())
+    }
+
+    fn set_potency(&mut self, duty_cycle: f64) -> Result<()> {
+        self.record(MotorCommand::Potency(duty_cycle));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use crate::gpio::motor_driver::{MotorDriver, SharedMotor};
+use std::panic;
+use std::sync::{Mutex, Once, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long the panic hook retries a motor another thread is commanding
+const HOOK_WAIT: Duration = Duration::from_millis(100);
+
+static MOTORS: Mutex<Vec<SharedMotor>> = Mutex::new(Vec::new());
+static HOOK: Once = Once::new();
+
+/// Adds a motor to the set stopped on any panic or worker crash.
+pub fn register(motor: SharedMotor) {
+    MOTORS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(motor);
+}
+
+/// Stops every registered motor, waiting for threads using them and ignoring poisoned locks.
+pub fn stop_all() {
+    for mut motor in registered() {
+        if let Err(e) = motor.stop() {
+            eprintln!("Couldn't stop motor: {}", e);
+        }
+    }
+}
+
+/// Chains a panic hook stopping every registered motor before the default report, installed once per process.
+pub fn install_panic_hook() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            stop_without_blocking();
+            previous(info);
+        }));
+    });
+}
+
+fn registered() -> Vec<SharedMotor> {
+    MOTORS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+// The panicking thread may hold a motor lock itself, so never block on it here:
+// motors still busy after the wait are stopped by the WorkerGuard once the thread unwinds
+fn stop_without_blocking() {
+    let mut pending = registered();
+    let deadline = Instant::now() + HOOK_WAIT;
+
+    loop {
+        pending.retain(|motor| match motor.try_stop() {
+            Some(Err(e)) => {
+                eprintln!("Couldn't stop motor: {}", e);
+                false
+            }
+            Some(Ok(_)) => false,
+            None => true,
+        });
+
+        if pending.is_empty() || Instant::now() >= deadline {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Held by a worker thread for its whole life, stops every motor if the thread dies by panicking.
+///
+/// Declare it before any lock guard of the thread so it is dropped after them while unwinding.
+pub struct WorkerGuard {
+    name: String,
+}
+
+impl WorkerGuard {
+    pub fn new(name: impl Into<String>) -> Self {
+        WorkerGuard { name: name.into() }
+    }
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            eprintln!("{} thread died, stopping every motor", self.name);
+            stop_all();
+        }
+    }
+}
@@ -1,56 +1,103 @@
 use crate::common::Direction;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
 
-const KP: f64 = 0.005;
-const KI: f64 = 0.0;
-const KD: f64 = 0.01;
-const T: f64 = 1.0;
-const MAX_POTENCY: f64 = 100.0;
-const MIN_POTENCY: f64 = -100.0;
+// Output range, a duty cycle signed by the direction
+const MAX_OUTPUT: f64 = 1.0;
+
+/// Gains of the position controller of a car, in duty cycle per encoder tick and seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PidGains {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    /// Time constant of the low-pass filter on the derivative, in seconds
+    pub derivative_filter: f64,
+}
+
+impl Default for PidGains {
+    fn default() -> Self {
+        // The gains the lab rig was tuned with, at its 100 ms control period
+        PidGains {
+            kp: 0.00005,
+            ki: 0.0,
+            kd: 0.00001,
+            derivative_filter: 0.1,
+        }
+    }
+}
 
 pub struct PidController {
-    total_error: f64,
-    last_error: f64,
+    gains: PidGains,
+    /// Integral term, kept with its gain so a change of gains does not bump the output
+    integral: f64,
+    /// Filtered derivative of the measurement
+    derivative: f64,
+    last: Option<(f64, Instant)>,
 }
 
 impl PidController {
-    pub fn new() -> Self {
+    pub fn new(gains: PidGains) -> Self {
         PidController {
-            total_error: 0.0,
-            last_error: 0.0,
+            gains,
+            integral: 0.0,
+            derivative: 0.0,
+            last: None,
         }
     }
 
-    pub fn get_control_signal(&mut self, origin: i32, target: i32) -> (f64, Direction) {
-        let error = target as f64 - origin as f64;
+    /// Forgets the previous trip, the next update only has the proportional term
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.derivative = 0.0;
+        self.last = None;
+    }
+
+    /// Signed duty cycle moving the measurement towards the setpoint, between -1.0 and 1.0
+    ///
+    /// The derivative acts on the measurement so a new setpoint gives no kick, and the integral only
+    /// grows while the output is not saturated in the direction of the error.
+    pub fn update(&mut self, measurement: f64, setpoint: f64, now: Instant) -> f64 {
+        let error = setpoint - measurement;
+        let mut integral = self.integral;
 
-        self.total_error += error;
+        if let Some((last, at)) = self.last {
+            let dt = now.saturating_duration_since(at).as_secs_f64();
 
-        if self.total_error >= MAX_POTENCY {
-            self.total_error = MAX_POTENCY;
-        } else if self.total_error <= MIN_POTENCY {
-            self.total_error = MIN_POTENCY;
+            if dt > 0.0 {
+                let rate = -(measurement - last) / dt;
+                let alpha = dt / (self.gains.derivative_filter + dt);
+
+                self.derivative += alpha * (rate - self.derivative);
+                integral += self.gains.ki * error * dt;
+            }
         }
 
-        let delta_error = error - self.last_error;
+        self.last = Some((measurement, now));
 
-        let mut control_signal = KP * error + (KI * T) * self.total_error + (KD / T) * delta_error;
+        let proportional = self.gains.kp * error;
+        let derivative = self.gains.kd * self.derivative;
+        let output = proportional + integral + derivative;
 
-        if control_signal >= MAX_POTENCY {
-            control_signal = MAX_POTENCY;
-        } else if control_signal <= MIN_POTENCY {
-            control_signal = MIN_POTENCY;
+        // Conditional integration: winding further into the saturation is dropped
+        if output.abs() <= MAX_OUTPUT || error.signum() != output.signum() {
+            self.integral = integral;
         }
 
-        self.last_error = error;
+        (proportional + self.integral + derivative).clamp(-MAX_OUTPUT, MAX_OUTPUT)
+    }
 
-        let direction = if control_signal >= 0.0 {
+    /// Duty cycle and direction moving the car from `origin` to `target`, measuring the time since the last call
+    pub fn get_control_signal(&mut self, origin: i32, target: i32) -> (f64, Direction) {
+        let output = self.update(origin as f64, target as f64, Instant::now());
+
+        let direction = if output >= 0.0 {
             Direction::Up
         } else {
             Direction::Down
         };
 
-        let potency = control_signal.abs() / 100.0;
-
-        (potency, direction)
+        (output.abs(), direction)
     }
 }
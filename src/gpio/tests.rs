@@ -1,43 +1,59 @@
-use crate::common::{Direction, Elevator};
-use crate::gpio::{engine_control::EngineControl, pid::PidController};
+use crate::common::{Direction, Floor};
+use crate::error::Result;
+use crate::gpio::floor_sensors::{FloorSensors, ScriptedFloorSensors};
+use crate::gpio::motor_driver::{MotorCommand, MotorDriver, RecordingMotor, SharedMotor};
+use crate::gpio::motor_safety::{self, WorkerGuard};
+use crate::gpio::pid::{PidController, PidGains};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[test]
 fn move_elevator() {
     // Arrange
-    let mut elevator1 = EngineControl::new(Elevator::One);
-    let mut elevator2 = EngineControl::new(Elevator::Two);
+    let mut elevator1 = RecordingMotor::new();
+    let mut elevator2 = RecordingMotor::new();
 
-    // Act
-    elevator1.set_direction(Direction::Up);
-    elevator1.set_potency(1.0);
-
-    elevator2.set_direction(Direction::Up);
-    elevator2.set_potency(1.0);
+    let log1 = elevator1.log();
+    let log2 = elevator2.log();
 
-    thread::sleep(Duration::from_secs(1));
+    // Act
+    for motor in [&mut elevator1 as &mut dyn MotorDriver, &mut elevator2] {
+        motor.set_direction(Direction::Up).unwrap();
+        motor.set_potency(1.0).unwrap();
 
-    elevator1.set_direction(Direction::Down);
-    elevator1.set_potency(1.0);
+        motor.set_direction(Direction::Down).unwrap();
+        motor.set_potency(1.0).unwrap();
 
-    elevator2.set_direction(Direction::Down);
-    elevator2.set_potency(1.0);
+        // Cleanup
+        motor.set_direction(Direction::Stop).unwrap();
+        motor.set_potency(0.0).unwrap();
+    }
 
-    thread::sleep(Duration::from_secs(1));
+    // Assert
+    for log in [log1, log2] {
+        let commands: Vec<MotorCommand> = log.commands().into_iter().map(|(_, c)| c).collect();
 
-    // Cleanup
-    elevator1.set_direction(Direction::Stop);
-    elevator1.set_potency(0.0);
+        assert_eq!(
+            commands,
+            vec![
+                MotorCommand::Direction(Direction::Up),
+                MotorCommand::Potency(1.0),
+                MotorCommand::Direction(Direction::Down),
+                MotorCommand::Potency(1.0),
+                MotorCommand::Direction(Direction::Stop),
+                MotorCommand::Potency(0.0),
+            ]
+        );
 
-    elevator2.set_direction(Direction::Stop);
-    elevator2.set_potency(0.0);
+        assert!(log.is_stopped());
+        assert!(log.commands().windows(2).all(|w| w[0].0 <= w[1].0));
+    }
 }
 
 #[test]
 fn pid() {
     // Arrange
-    let mut pid = PidController::new();
+    let mut pid = PidController::new(PidGains::default());
 
     // Act
     let (potency_1, direction_1) = pid.get_control_signal(0, 25000);
@@ -50,3 +66,136 @@ fn pid() {
     assert_eq!(potency_2, 1.0);
     assert_eq!(direction_2, Direction::Down);
 }
+
+#[test]
+fn pid_stops_integrating_while_saturated() {
+    // Arrange
+    let gains = PidGains {
+        kp: 0.0001,
+        ki: 0.0001,
+        kd: 0.0,
+        derivative_filter: 0.1,
+    };
+    let mut pid = PidController::new(gains);
+    let start = Instant::now();
+    let at = |millis: u64| start + Duration::from_millis(millis);
+
+    // Act
+    // 20000 ticks away the proportional term alone saturates
+    for step in 0..50 {
+        pid.update(0.0, 20000.0, at(step * 100));
+    }
+
+    // Once close the output follows the error again instead of unwinding a huge integral
+    let close = pid.update(19000.0, 20000.0, at(5000));
+
+    pid.reset();
+    let after_reset = pid.update(19000.0, 20000.0, at(5100));
+
+    // Assert
+    assert!(close < 0.2);
+    assert!((after_reset - 0.1).abs() < 1e-9);
+}
+
+#[test]
+fn pid_derivative_acts_on_the_measurement() {
+    // Arrange
+    let gains = PidGains {
+        kp: 0.0,
+        ki: 0.0,
+        kd: 0.0001,
+        derivative_filter: 0.0,
+    };
+    let mut pid = PidController::new(gains);
+    let start = Instant::now();
+
+    pid.update(1000.0, 1000.0, start);
+
+    // Act
+    let new_setpoint = pid.update(1000.0, 5000.0, start + Duration::from_millis(100));
+    // Moving up at 2000 ticks per second over the real 100 ms period
+    let moving = pid.update(1200.0, 5000.0, start + Duration::from_millis(200));
+
+    // Assert
+    assert_eq!(new_setpoint, 0.0);
+    assert!((moving + 0.2).abs() < 1e-9);
+}
+
+#[test]
+fn scripted_sensors() {
+    // Arrange
+    let sensors = ScriptedFloorSensors::new(4);
+    let levels = sensors.levels();
+
+    // Act
+    levels.set(1, true);
+
+    // Assert
+    assert!(sensors.is_at(Floor(1)));
+    assert!(!sensors.is_at(Floor(0)));
+    assert!(!sensors.is_at(Floor(4)));
+
+    levels.set_all(&[false, false, false, true]);
+
+    assert!(!sensors.is_at(Floor(1)));
+    assert!(sensors.is_at(Floor(3)));
+}
+
+#[test]
+fn scripted_sensors_landing_count() {
+    // Arrange
+    let short = ScriptedFloorSensors::new(2);
+    let tall = ScriptedFloorSensors::new(6);
+
+    // Act
+    short.levels().set_all(&[true, true]);
+    tall.levels().set(5, true);
+
+    // Assert
+    assert_eq!(short.landings(), 2);
+    assert!(short.is_at(Floor(1)));
+    assert!(!short.is_at(Floor(2)));
+
+    assert_eq!(tall.landings(), 6);
+    assert!(tall.is_active(5));
+}
+
+// Motor whose driver panics on an out of range duty cycle, poisoning any lock held around it
+struct FaultyMotor(RecordingMotor);
+
+impl MotorDriver for FaultyMotor {
+    fn set_direction(&mut self, direction: Direction) -> Result<()> {
+        self.0.set_direction(direction)
+    }
+
+    fn set_potency(&mut self, duty_cycle: f64) -> Result<()> {
+        assert!(duty_cycle <= 1.0, "duty cycle out of range");
+
+        self.0.set_potency(duty_cycle)
+    }
+}
+
+#[test]
+fn crashed_worker_stops_motors() {
+    // Arrange
+    let recording = RecordingMotor::new();
+    let log = recording.log();
+
+    let motor = SharedMotor::new(FaultyMotor(recording));
+    motor_safety::register(motor.clone());
+
+    // Act
+    let mut worker_motor = motor.clone();
+    let worker = thread::spawn(move || {
+        let _guard = WorkerGuard::new("Test worker");
+
+        worker_motor.set_direction(Direction::Up).unwrap();
+        worker_motor.set_potency(0.5).unwrap();
+        worker_motor.set_potency(2.0).unwrap();
+    });
+
+    // Assert
+    assert!(worker.join().is_err());
+    assert!(log.is_stopped());
+    assert!(motor.try_stop().is_some_and(|result| result.is_ok()));
+}
@@ -1,74 +1,103 @@
 use crate::common::Elevator;
+use crate::error::{DeviceError, Result};
 use bme280::i2c::BME280 as Device;
 use rppal::{hal::Delay, i2c::I2c};
-use std::fs::{metadata, read_to_string};
+use std::fs::{read_dir, read_to_string};
+use std::path::PathBuf;
 
-const TEMPERATURE_FILE_1: &str = "/sys/bus/i2c/devices/i2c-1/1-0076/iio:device0/in_temp_input";
-const TEMPERATURE_FILE_2: &str = "/sys/bus/i2c/devices/i2c-1/1-0077/iio:device1/in_temp_input";
+// Sysfs folder of a device on the I2C bus of the header pins, e.g. 1-0076 for 0x76
+fn device_folder(address: u8) -> PathBuf {
+    PathBuf::from(format!("/sys/bus/i2c/devices/i2c-1/1-{:04x}", address))
+}
 
-fn file_exists(file: &str) -> bool {
-    metadata(file).is_ok()
+// Reading exposed by the kernel module, when it is bound to the device
+fn temperature_file(address: u8) -> Option<PathBuf> {
+    read_dir(device_folder(address))
+        .ok()?
+        .flatten()
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("iio:device")
+        })
+        .map(|entry| entry.path().join("in_temp_input"))
+        .filter(|file| file.exists())
 }
 
-struct I2cSensors {
-    sensor1: Device<I2c>,
-    sensor2: Device<I2c>,
-    delay: Delay,
+enum Sensor {
+    Kernel(PathBuf),
+    I2c(Device<I2c>),
+    Missing,
 }
 
 pub struct BME280 {
-    sensors: Option<I2cSensors>,
+    sensors: Vec<Sensor>,
+    delay: Delay,
 }
 
 impl BME280 {
-    pub fn new() -> Self {
-        // If TEMPERATURE_FILE_1 and TEMPERATURE_FILE_2 exist, then the BME280 is connected as kernel module
-        if file_exists(TEMPERATURE_FILE_1) && file_exists(TEMPERATURE_FILE_2) {
-            Self { sensors: None }
-        }
-        // If not, then the BME280 is connected as I2C device
-        else {
-            let i2c = I2c::new().unwrap();
-            let mut bme280_1 = Device::new_primary(i2c);
+    /// Opens the sensor of each car by its I2C address, None for a car without one
+    pub fn new(addresses: &[Option<u8>]) -> Result<Self> {
+        let mut delay = Delay::new();
 
-            let i2c = I2c::new().unwrap();
-            let mut bme280_2 = Device::new_secondary(i2c);
+        let sensors = addresses
+            .iter()
+            .map(|address| {
+                let Some(address) = *address else {
+                    return Ok(Sensor::Missing);
+                };
 
-            let mut delay = Delay::new();
+                // If the kernel module is bound to the BME280, it must be read through sysfs
+                if let Some(file) = temperature_file(address) {
+                    return Ok(Sensor::Kernel(file));
+                }
 
-            bme280_1.init(&mut delay).unwrap();
-            bme280_2.init(&mut delay).unwrap();
+                // If not, then the BME280 is connected as I2C device
+                let mut device = Device::new(I2c::new()?, address);
 
-            Self {
-                sensors: Some(I2cSensors {
-                    sensor1: bme280_1,
-                    sensor2: bme280_2,
-                    delay,
-                }),
-            }
-        }
+                device
+                    .init(&mut delay)
+                    .map_err(|e| DeviceError::Bme280(format!("{:?}", e)))?;
+
+                Ok(Sensor::I2c(device))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { sensors, delay })
+    }
+
+    /// Whether the car has a sensor to measure
+    pub fn has_sensor(&self, elevator: Elevator) -> bool {
+        matches!(
+            self.sensors.get(elevator.index()),
+            Some(Sensor::Kernel(_) | Sensor::I2c(_))
+        )
     }
 
-    pub fn measure_temperature(&mut self, elevator: Elevator) -> f32 {
-        match &mut self.sensors {
-            Some(sensors) => match elevator {
-                Elevator::One => &mut sensors.sensor1,
-                Elevator::Two => &mut sensors.sensor2,
+    pub fn measure_temperature(&mut self, elevator: Elevator) -> Result<f32> {
+        let temperature = match self.sensors.get_mut(elevator.index()) {
+            Some(Sensor::I2c(device)) => {
+                device
+                    .measure(&mut self.delay)
+                    .map_err(|e| DeviceError::Bme280(format!("{:?}", e)))?
+                    .temperature
             }
-            .measure(&mut sensors.delay)
-            .unwrap()
-            .temperature
-            .round(),
-            None => read_to_string(match elevator {
-                Elevator::One => TEMPERATURE_FILE_1,
-                Elevator::Two => TEMPERATURE_FILE_2,
-            })
-            .unwrap()
-            .trim()
-            .parse()
-            .map(|temp: f32| (temp + 5.0) / 1000.0)
-            .unwrap()
-            .round(),
-        }
+            Some(Sensor::Kernel(file)) => read_to_string(file)
+                .map_err(|e| DeviceError::Temperature(e.to_string()))?
+                .trim()
+                .parse()
+                .map(|temp: f32| (temp + 5.0) / 1000.0)
+                .map_err(|e| DeviceError::Temperature(e.to_string()))?,
+            Some(Sensor::Missing) | None => {
+                return Err(DeviceError::Temperature(format!(
+                    "elevator {} has no sensor",
+                    elevator
+                ))
+                .into())
+            }
+        };
+
+        Ok(temperature.round())
     }
 }
@@ -0,0 +1,102 @@
+use crate::common::{Direction, Elevator};
+use crate::elevator::doors::DoorState;
+use crate::error::{DeviceError, Result};
+
+/// Anything showing the state of the cars: the SSD1306 on the Raspberry Pi, a mock, a simulator...
+pub trait StatusDisplay: Send {
+    fn update_temperature(&mut self, elevator: Elevator, temperature: f32) -> Result<()>;
+
+    /// Name of the landing the car is at, up to 2 characters
+    fn update_floor(&mut self, elevator: Elevator, floor: &str) -> Result<()>;
+
+    fn update_direction(&mut self, elevator: Elevator, direction: Direction) -> Result<()>;
+
+    fn update_service(&mut self, elevator: Elevator, in_service: bool) -> Result<()>;
+
+    fn update_doors(&mut self, elevator: Elevator, doors: DoorState) -> Result<()>;
+
+    /// Short message under the car, up to 14 characters, empty to clear it
+    fn update_notice(&mut self, elevator: Elevator, notice: &str) -> Result<()>;
+}
+
+/// What a [`MemoryDisplay`] shows of one car.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CarScreen {
+    pub direction: Direction,
+    pub floor: String,
+    pub temperature: f32,
+    pub in_service: bool,
+    pub notice: String,
+    pub doors: DoorState,
+}
+
+/// Mock display keeping the last state shown of each car.
+pub struct MemoryDisplay {
+    cars: Vec<CarScreen>,
+}
+
+impl MemoryDisplay {
+    pub fn new(cars: usize) -> Self {
+        MemoryDisplay {
+            cars: vec![
+                CarScreen {
+                    direction: Direction::Stop,
+                    floor: String::new(),
+                    temperature: 0.0,
+                    in_service: true,
+                    notice: String::new(),
+                    doors: DoorState::Closed,
+                };
+                cars
+            ],
+        }
+    }
+
+    pub fn car(&self, elevator: Elevator) -> &CarScreen {
+        &self.cars[elevator.index()]
+    }
+
+    fn screen(&mut self, elevator: Elevator) -> Result<&mut CarScreen> {
+        self.cars.get_mut(elevator.index()).ok_or_else(|| {
+            DeviceError::Display(format!("no column for elevator {}", elevator)).into()
+        })
+    }
+}
+
+impl StatusDisplay for MemoryDisplay {
+    fn update_temperature(&mut self, elevator: Elevator, temperature: f32) -> Result<()> {
+        self.screen(elevator)?.temperature = temperature;
+
+        Ok(())
+    }
+
+    fn update_floor(&mut self, elevator: Elevator, floor: &str) -> Result<()> {
+        self.screen(elevator)?.floor = floor.to_string();
+
+        Ok(())
+    }
+
+    fn update_direction(&mut self, elevator: Elevator, direction: Direction) -> Result<()> {
+        self.screen(elevator)?.direction = direction;
+
+        Ok(())
+    }
+
+    fn update_service(&mut self, elevator: Elevator, in_service: bool) -> Result<()> {
+        self.screen(elevator)?.in_service = in_service;
+
+        Ok(())
+    }
+
+    fn update_doors(&mut self, elevator: Elevator, doors: DoorState) -> Result<()> {
+        self.screen(elevator)?.doors = doors;
+
+        Ok(())
+    }
+
+    fn update_notice(&mut self, elevator: Elevator, notice: &str) -> Result<()> {
+        self.screen(elevator)?.notice = notice.to_string();
+
+        Ok(())
+    }
+}
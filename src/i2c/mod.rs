@@ -1,4 +1,5 @@
 pub mod bme280;
+pub mod display;
 pub mod ssd1306;
 
 #[cfg(test)]
@@ -1,6 +1,9 @@
-use crate::common::{Direction, Elevator, Floor};
+use crate::common::{Direction, Elevator};
+use crate::elevator::doors::DoorState;
+use crate::error::{DeviceError, Error, Result};
+use crate::i2c::display::StatusDisplay;
 use embedded_graphics::{
-    mono_font::{ascii, MonoTextStyleBuilder},
+    mono_font::{ascii, MonoFont, MonoTextStyleBuilder},
     pixelcolor::BinaryColor,
     prelude::*,
     primitives::{Line, PrimitiveStyle, Triangle},
@@ -9,21 +12,42 @@ use embedded_graphics::{
 use rppal::i2c::I2c;
 use ssd1306::{mode::BufferedGraphicsMode, prelude::*, I2CDisplayInterface, Ssd1306};
 
+const WIDTH: i32 = 128;
+
+// Columns narrower than half the screen use a smaller font for the floor and shorter texts
+const WIDE_COLUMN: i32 = 64;
+
 struct ElevatorState {
     direction: Direction,
-    floor: Floor,
+    /// Name of the landing, from the building topology
+    floor: String,
     temperature: f32,
+    in_service: bool,
+    notice: String,
+    doors: DoorState,
+}
+
+/// Where the texts of a car are drawn, relative to the left edge of its column.
+struct Layout {
+    padding: i32,
+    title: String,
+    floor_font: &'static MonoFont<'static>,
+    floor_x: i32,
+    /// Width of the direction triangles
+    arrow: i32,
+    /// Characters of the notice that fit in the column
+    notice_len: usize,
 }
 
 pub struct SSD1306 {
     display: Ssd1306<I2CInterface<I2c>, DisplaySize128x64, BufferedGraphicsMode<DisplaySize128x64>>,
-    elevator_1: ElevatorState,
-    elevator_2: ElevatorState,
+    /// One column per car, from the left
+    elevators: Vec<ElevatorState>,
 }
 
 impl SSD1306 {
-    pub fn new() -> Self {
-        let i2c = I2c::new().unwrap();
+    pub fn new(cars: usize) -> Result<Self> {
+        let i2c = I2c::new()?;
 
         let mut ssd1306 = Self {
             display: Ssd1306::new(
@@ -32,241 +56,302 @@ impl SSD1306 {
                 DisplayRotation::Rotate0,
             )
             .into_buffered_graphics_mode(),
-            elevator_1: ElevatorState {
-                direction: Direction::Up,
-                floor: Floor::First,
-                temperature: 25.0,
-            },
-            elevator_2: ElevatorState {
-                direction: Direction::Down,
-                floor: Floor::Ground,
-                temperature: 30.0,
-            },
+            elevators: (0..cars.max(1))
+                .map(|_| ElevatorState {
+                    direction: Direction::Stop,
+                    floor: "?".to_string(),
+                    temperature: 0.0,
+                    in_service: true,
+                    notice: String::new(),
+                    doors: DoorState::Closed,
+                })
+                .collect(),
         };
 
-        ssd1306.display.init().unwrap();
-        ssd1306.refresh_screen();
+        ssd1306.display.init().map_err(display_error)?;
+        ssd1306.refresh_screen()?;
+
+        Ok(ssd1306)
+    }
 
-        ssd1306
+    fn column_width(&self) -> i32 {
+        WIDTH / self.elevators.len() as i32
     }
 
-    fn refresh_screen(&mut self) {
-        self.display.clear(BinaryColor::Off).unwrap();
+    fn layout(&self, index: usize) -> Layout {
+        let width = self.column_width();
+
+        if width >= WIDE_COLUMN {
+            Layout {
+                padding: 5,
+                title: format!("Elevador {}", Elevator(index)),
+                floor_font: &ascii::FONT_10X20,
+                floor_x: 44,
+                arrow: 20,
+                notice_len: 14,
+            }
+        } else {
+            Layout {
+                padding: 3,
+                title: format!("E{}", Elevator(index)),
+                floor_font: &ascii::FONT_6X10,
+                floor_x: width - 14,
+                arrow: 14,
+                notice_len: (width as usize - 4) / 4,
+            }
+        }
+    }
 
-        self.render_background();
-        self.render_temperature();
-        self.render_floor();
-        self.render_direction();
+    fn refresh_screen(&mut self) -> Result<()> {
+        self.display
+            .clear(BinaryColor::Off)
+            .map_err(display_error)?;
+
+        for index in 0..self.elevators.len() {
+            let left = index as i32 * self.column_width();
+            let layout = self.layout(index);
+
+            self.render_background(left, &layout)?;
+            self.render_temperature(index, left, &layout)?;
+            self.render_floor(index, left, &layout)?;
+            self.render_direction(index, left, &layout)?;
+            self.render_doors(index, left, &layout)?;
+            self.render_service(index, left, &layout)?;
+        }
 
-        self.display.flush().unwrap();
+        self.display.flush().map_err(display_error)
     }
 
-    fn render_background(&mut self) {
-        // Create a line separating the screen vertically in half
-        Line::new(Point::new(64, 0), Point::new(64, 64))
-            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
-            .draw(&mut self.display)
-            .unwrap();
+    fn render_background(&mut self, left: i32, layout: &Layout) -> Result<()> {
+        // Create a line separating the column from the previous one
+        if left > 0 {
+            Line::new(Point::new(left, 0), Point::new(left, 64))
+                .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                .draw(&mut self.display)
+                .map_err(display_error)?;
+        }
 
         let text_style = MonoTextStyleBuilder::new()
             .font(&ascii::FONT_4X6)
             .text_color(BinaryColor::On)
             .build();
 
-        // Write "Elevador 1" on the top left of the first half with a 5px padding
-        Text::new("Elevador 1", Point::new(5, 5), text_style)
-            .draw(&mut self.display)
-            .unwrap();
+        // Write the name of the car on the top left of its column
+        Text::new(
+            &layout.title,
+            Point::new(left + layout.padding, 5),
+            text_style,
+        )
+        .draw(&mut self.display)
+        .map_err(display_error)?;
 
-        // Write "Elevador 2" on the top left of the second half with a 5px padding
-        Text::new("Elevador 2", Point::new(69, 5), text_style)
-            .draw(&mut self.display)
-            .unwrap();
+        Ok(())
     }
 
-    fn render_temperature(&mut self) {
-        let text_1 = format!("{:.0}'C", self.elevator_1.temperature);
-        let text_2 = format!("{:.0}'C", self.elevator_2.temperature);
+    fn render_temperature(&mut self, index: usize, left: i32, layout: &Layout) -> Result<()> {
+        let text = format!("{:.0}'C", self.elevators[index].temperature);
 
-        // The temperature is written below the title with a padding of 5px
-        let point_1 = Point::new(5, 15);
-        let point_2 = Point::new(69, 15);
+        // The temperature is written below the title
+        let point = Point::new(left + layout.padding, 15);
 
         let text_style = MonoTextStyleBuilder::new()
             .font(&ascii::FONT_4X6)
             .text_color(BinaryColor::On)
             .build();
 
-        Text::new(&text_1, point_1, text_style)
+        Text::new(&text, point, text_style)
             .draw(&mut self.display)
-            .unwrap();
+            .map_err(display_error)?;
 
-        Text::new(&text_2, point_2, text_style)
-            .draw(&mut self.display)
-            .unwrap();
+        Ok(())
     }
 
-    fn render_floor(&mut self) {
-        let text_1 = match self.elevator_1.floor {
-            Floor::Ground => "T",
-            Floor::First => "1",
-            Floor::Second => "2",
-            Floor::Third => "3",
-            Floor::Undefined => "?",
-        };
+    fn render_floor(&mut self, index: usize, left: i32, layout: &Layout) -> Result<()> {
+        let text = self.elevators[index].floor.clone();
 
-        let text_2 = match self.elevator_2.floor {
-            Floor::Ground => "T",
-            Floor::First => "1",
-            Floor::Second => "2",
-            Floor::Third => "3",
-            Floor::Undefined => "?",
-        };
-
-        // The floor is written on the center right of the respective elevator with a 10px padding
-        let point_1 = Point::new(44, 42);
-        let point_2 = Point::new(108, 42);
+        // The floor is written on the center right of the column
+        let point = Point::new(left + layout.floor_x, 42);
 
         let text_style = MonoTextStyleBuilder::new()
-            .font(&ascii::FONT_10X20)
+            .font(layout.floor_font)
             .text_color(BinaryColor::On)
             .build();
 
-        Text::new(&text_1, point_1, text_style)
+        Text::new(&text, point, text_style)
             .draw(&mut self.display)
-            .unwrap();
+            .map_err(display_error)?;
 
-        Text::new(&text_2, point_2, text_style)
-            .draw(&mut self.display)
-            .unwrap();
+        Ok(())
     }
 
-    fn render_direction(&mut self) {
-        // The direction is represented by two triangles on the bottom left of the respective elevator
+    fn render_direction(&mut self, index: usize, left: i32, layout: &Layout) -> Result<()> {
+        // The direction is represented by two triangles on the bottom left of the column
         // The triangle filled with the color represents the current direction
         // The triangle outlined with the color represents the opposite direction
-        let upper_triangle_1 =
-            Triangle::new(Point::new(15, 30), Point::new(25, 35), Point::new(5, 35));
-        let upper_triangle_2 =
-            Triangle::new(Point::new(79, 30), Point::new(89, 35), Point::new(69, 35));
+        let x = left + layout.padding;
+        let half = layout.arrow / 2;
 
-        let lower_triangle_1 =
-            Triangle::new(Point::new(15, 44), Point::new(25, 39), Point::new(5, 39));
-        let lower_triangle_2 =
-            Triangle::new(Point::new(79, 44), Point::new(89, 39), Point::new(69, 39));
+        let upper_triangle = Triangle::new(
+            Point::new(x + half, 30),
+            Point::new(x + layout.arrow, 35),
+            Point::new(x, 35),
+        );
+
+        let lower_triangle = Triangle::new(
+            Point::new(x + half, 44),
+            Point::new(x + layout.arrow, 39),
+            Point::new(x, 39),
+        );
 
         let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
         let fill = PrimitiveStyle::with_fill(BinaryColor::On);
 
-        match self.elevator_1.direction {
-            Direction::Up => {
-                upper_triangle_1
-                    .into_styled(fill)
-                    .draw(&mut self.display)
-                    .unwrap();
-                lower_triangle_1
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-            }
-            Direction::Down => {
-                upper_triangle_1
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-                lower_triangle_1
-                    .into_styled(fill)
-                    .draw(&mut self.display)
-                    .unwrap();
-            }
-            Direction::Stop => {
-                upper_triangle_1
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-                lower_triangle_1
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-            }
-        }
+        let (upper, lower) = match self.elevators[index].direction {
+            Direction::Up => (fill, stroke),
+            Direction::Down => (stroke, fill),
+            Direction::Stop => (stroke, stroke),
+        };
 
-        match self.elevator_2.direction {
-            Direction::Up => {
-                upper_triangle_2
-                    .into_styled(fill)
-                    .draw(&mut self.display)
-                    .unwrap();
-                lower_triangle_2
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-            }
-            Direction::Down => {
-                upper_triangle_2
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-                lower_triangle_2
-                    .into_styled(fill)
-                    .draw(&mut self.display)
-                    .unwrap();
-            }
-            Direction::Stop => {
-                upper_triangle_2
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-                lower_triangle_2
-                    .into_styled(stroke)
-                    .draw(&mut self.display)
-                    .unwrap();
-            }
-        }
+        upper_triangle
+            .into_styled(upper)
+            .draw(&mut self.display)
+            .map_err(display_error)?;
+        lower_triangle
+            .into_styled(lower)
+            .draw(&mut self.display)
+            .map_err(display_error)?;
+
+        Ok(())
     }
 
-    pub fn update_temperature(&mut self, elevator: Elevator, temperature: f32) {
-        let elevator = match elevator {
-            Elevator::One => &mut self.elevator_1,
-            Elevator::Two => &mut self.elevator_2,
+    fn render_doors(&mut self, index: usize, left: i32, layout: &Layout) -> Result<()> {
+        // Drawn as the door panels seen from the landing
+        let text = match self.elevators[index].doors {
+            DoorState::Closed => "[]",
+            DoorState::Opening | DoorState::Reopening => "<>",
+            DoorState::Open => "[ ]",
+            DoorState::Closing => "><",
         };
 
+        let text_style = MonoTextStyleBuilder::new()
+            .font(&ascii::FONT_4X6)
+            .text_color(BinaryColor::On)
+            .build();
+
+        // The doors are drawn above the floor
+        Text::new(text, Point::new(left + layout.floor_x, 25), text_style)
+            .draw(&mut self.display)
+            .map_err(display_error)?;
+
+        Ok(())
+    }
+
+    fn render_service(&mut self, index: usize, left: i32, layout: &Layout) -> Result<()> {
+        let text_style = MonoTextStyleBuilder::new()
+            .font(&ascii::FONT_4X6)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let elevator = &self.elevators[index];
+
+        // Being out of service hides any other notice
+        let text: String = match elevator.in_service {
+            true => elevator.notice.as_str(),
+            false => "INOPERANTE",
+        }
+        .chars()
+        .take(layout.notice_len)
+        .collect();
+
+        // The notice is written on the bottom left of the column
+        Text::new(&text, Point::new(left + layout.padding, 58), text_style)
+            .draw(&mut self.display)
+            .map_err(display_error)?;
+
+        Ok(())
+    }
+
+    fn elevator(&mut self, elevator: Elevator) -> Result<&mut ElevatorState> {
+        self.elevators
+            .get_mut(elevator.index())
+            .ok_or_else(|| display_error(format!("no column for elevator {}", elevator)))
+    }
+}
+
+impl StatusDisplay for SSD1306 {
+    fn update_temperature(&mut self, elevator: Elevator, temperature: f32) -> Result<()> {
+        let elevator = self.elevator(elevator)?;
+
         if elevator.temperature == temperature {
-            return;
+            return Ok(());
         }
 
         elevator.temperature = temperature;
 
-        self.refresh_screen();
+        self.refresh_screen()
     }
 
-    pub fn update_floor(&mut self, elevator: Elevator, floor: Floor) {
-        let elevator = match elevator {
-            Elevator::One => &mut self.elevator_1,
-            Elevator::Two => &mut self.elevator_2,
-        };
+    fn update_floor(&mut self, elevator: Elevator, floor: &str) -> Result<()> {
+        let elevator = self.elevator(elevator)?;
 
         if elevator.floor == floor {
-            return;
+            return Ok(());
         }
 
-        elevator.floor = floor;
+        elevator.floor = floor.to_string();
 
-        self.refresh_screen();
+        self.refresh_screen()
     }
 
-    pub fn update_direction(&mut self, elevator: Elevator, direction: Direction) {
-        let elevator = match elevator {
-            Elevator::One => &mut self.elevator_1,
-            Elevator::Two => &mut self.elevator_2,
-        };
+    fn update_direction(&mut self, elevator: Elevator, direction: Direction) -> Result<()> {
+        let elevator = self.elevator(elevator)?;
 
         if elevator.direction == direction {
-            return;
+            return Ok(());
         }
 
         elevator.direction = direction;
 
-        self.refresh_screen();
+        self.refresh_screen()
     }
+
+    fn update_service(&mut self, elevator: Elevator, in_service: bool) -> Result<()> {
+        let elevator = self.elevator(elevator)?;
+
+        if elevator.in_service == in_service {
+            return Ok(());
+        }
+
+        elevator.in_service = in_service;
+
+        self.refresh_screen()
+    }
+
+    fn update_doors(&mut self, elevator: Elevator, doors: DoorState) -> Result<()> {
+        let elevator = self.elevator(elevator)?;
+
+        if elevator.doors == doors {
+            return Ok(());
+        }
+
+        elevator.doors = doors;
+
+        self.refresh_screen()
+    }
+
+    fn update_notice(&mut self, elevator: Elevator, notice: &str) -> Result<()> {
+        let elevator = self.elevator(elevator)?;
+
+        if elevator.notice == notice {
+            return Ok(());
+        }
+
+        elevator.notice = notice.to_string();
+
+        self.refresh_screen()
+    }
+}
+
+fn display_error(e: impl std::fmt::Debug) -> Error {
+    DeviceError::Display(format!("{:?}", e)).into()
 }
@@ -1,14 +1,14 @@
-use crate::common::{Direction, Elevator, Floor};
-use crate::i2c::{bme280::BME280, ssd1306::SSD1306};
+use crate::common::{Direction, Elevator};
+use crate::i2c::{bme280::BME280, display::StatusDisplay, ssd1306::SSD1306};
 
 #[test]
 fn measure() {
     // Arrange
-    let mut bme280 = BME280::new();
+    let mut bme280 = BME280::new(&[Some(0x76), Some(0x77)]).unwrap();
 
     // Act
-    let temperature_1 = bme280.measure_temperature(Elevator::One);
-    let temperature_2 = bme280.measure_temperature(Elevator::Two);
+    let temperature_1 = bme280.measure_temperature(Elevator(0)).unwrap();
+    let temperature_2 = bme280.measure_temperature(Elevator(1)).unwrap();
 
     // Assert
     assert!(temperature_1 > 0.0 && temperature_2 < 50.0);
@@ -18,26 +18,34 @@ fn measure() {
 #[test]
 fn screen_update() {
     // Arrange
-    let mut ssd1306 = SSD1306::new();
+    let mut ssd1306 = SSD1306::new(2).unwrap();
 
     // Act
-    ssd1306.update_temperature(Elevator::One, 25.0);
-    ssd1306.update_temperature(Elevator::Two, 30.0);
-
-    ssd1306.update_floor(Elevator::One, Floor::First);
-    ssd1306.update_floor(Elevator::Two, Floor::Ground);
-
-    ssd1306.update_direction(Elevator::One, Direction::Up);
-    ssd1306.update_direction(Elevator::Two, Direction::Down);
-
-    ssd1306.update_floor(Elevator::Two, Floor::Third);
-    ssd1306.update_direction(Elevator::Two, Direction::Stop);
-
-    ssd1306.update_floor(Elevator::One, Floor::Ground);
-    ssd1306.update_direction(Elevator::One, Direction::Stop);
-
-    ssd1306.update_temperature(Elevator::One, 20.0);
-    ssd1306.update_temperature(Elevator::Two, 25.0);
+    ssd1306.update_temperature(Elevator(0), 25.0).unwrap();
+    ssd1306.update_temperature(Elevator(1), 30.0).unwrap();
+
+    ssd1306.update_floor(Elevator(0), "1").unwrap();
+    ssd1306.update_floor(Elevator(1), "T").unwrap();
+
+    ssd1306
+        .update_direction(Elevator(0), Direction::Up)
+        .unwrap();
+    ssd1306
+        .update_direction(Elevator(1), Direction::Down)
+        .unwrap();
+
+    ssd1306.update_floor(Elevator(1), "3").unwrap();
+    ssd1306
+        .update_direction(Elevator(1), Direction::Stop)
+        .unwrap();
+
+    ssd1306.update_floor(Elevator(0), "T").unwrap();
+    ssd1306
+        .update_direction(Elevator(0), Direction::Stop)
+        .unwrap();
+
+    ssd1306.update_temperature(Elevator(0), 20.0).unwrap();
+    ssd1306.update_temperature(Elevator(1), 25.0).unwrap();
 
     // Assert
     // No panic
@@ -0,0 +1,10 @@
+// Hardware drivers are opened in `new()`, so a `Default` impl would hide side effects
+#![allow(clippy::new_without_default)]
+
+pub mod common;
+pub mod elevator;
+pub mod error;
+pub mod gpio;
+pub mod i2c;
+pub mod sim;
+pub mod uart;
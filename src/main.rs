@@ -1,35 +1,224 @@
-use elevator::elevator_control::ElevatorControl;
+use fse_trab_2::common::Elevator;
+use fse_trab_2::elevator::building::{Building, BUILDING_FILE};
+use fse_trab_2::elevator::elevator_control::ElevatorControl;
 use signal_hook::{
-    consts::{SIGINT, SIGTERM},
+    consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2},
+    flag,
     iterator::Signals,
 };
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process::exit;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
 
-mod common;
-mod elevator;
-mod gpio;
-mod i2c;
-mod uart;
+// Operator commands naming the car, e.g. `echo "recalibrate 3" > elevator.fifo`
+const COMMAND_FIFO: &str = "elevator.fifo";
+
+/// Request of the operator, by signal or through the command FIFO.
+enum Command {
+    Recalibrate(Elevator),
+    Reset(Elevator),
+    /// Every car in emergency
+    ResetAll,
+    Stop(&'static str),
+}
+
+// Creates the FIFO unless it is already there, e.g. left by a previous run
+fn create_fifo(path: &str) -> io::Result<()> {
+    let path = CString::new(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } != 0 {
+        let e = io::Error::last_os_error();
+
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+// Cars are numbered from 1 like on the panel
+fn parse_command(line: &str, cars: usize) -> Result<Option<Command>, String> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+
+    let parse_car = |value: &str| {
+        value
+            .parse::<usize>()
+            .ok()
+            .filter(|car| (1..=cars).contains(car))
+            .map(|car| Elevator(car - 1))
+            .ok_or_else(|| format!("Invalid elevator: {}, expected 1 to {}", value, cars))
+    };
+
+    match args.as_slice() {
+        ["recalibrate", car] => Ok(Some(Command::Recalibrate(parse_car(car)?))),
+        ["reset", car] => Ok(Some(Command::Reset(parse_car(car)?))),
+        ["reset"] => Ok(Some(Command::ResetAll)),
+        [] => Ok(None),
+        _ => Err(format!("Unknown command: {}", line)),
+    }
+}
+
+// Each writer opens, writes its lines and closes the FIFO, which is then opened again for the next one
+fn read_commands(commands: Sender<Command>, cars: usize) {
+    loop {
+        let fifo = match File::open(COMMAND_FIFO) {
+            Ok(fifo) => fifo,
+            Err(e) => {
+                eprintln!("Couldn't open {}, commands disabled: {}", COMMAND_FIFO, e);
+                return;
+            }
+        };
+
+        for line in BufReader::new(fifo).lines().map_while(Result::ok) {
+            match parse_command(&line, cars) {
+                Ok(Some(command)) => {
+                    if commands.send(command).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+}
 
 fn main() {
-    let mut elevator = ElevatorControl::new();
+    let building = match Building::load(BUILDING_FILE) {
+        Ok(building) => building,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", BUILDING_FILE, e);
+            exit(1);
+        }
+    };
+
+    let cars = building.cars.len();
 
-    elevator.init();
+    let mut elevator = match ElevatorControl::new(building) {
+        Ok(elevator) => elevator,
+        Err(e) => {
+            eprintln!("Couldn't open the elevator hardware: {}", e);
+            exit(1);
+        }
+    };
+
+    // Calibration runs before the signal loop below, so let the signals abort it meanwhile
+    for signal in [SIGINT, SIGTERM] {
+        flag::register(signal, elevator.calibration_abort_flag()).unwrap();
+    }
+
+    if let Err(e) = elevator.init() {
+        eprintln!("Couldn't start the elevator: {}", e);
+
+        if let Err(e) = elevator.stop() {
+            eprintln!("Couldn't stop elevator cleanly: {}", e);
+        }
+
+        exit(1);
+    }
+
+    // A signal received during init() but outside a calibration is not seen by the loop below
+    if elevator.calibration_abort_flag().load(Relaxed) {
+        println!("Interrupted while starting, shutting down...");
+
+        if let Err(e) = elevator.stop() {
+            eprintln!("Couldn't stop elevator cleanly: {}", e);
+        }
+
+        return;
+    }
+
+    let (sender, commands) = mpsc::channel();
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGUSR1, SIGUSR2, SIGHUP]).unwrap();
+
+    {
+        let sender = sender.clone();
+
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                let command = match signal {
+                    SIGUSR1 => Command::Recalibrate(Elevator(0)),
+                    SIGUSR2 if cars > 1 => Command::Recalibrate(Elevator(1)),
+                    SIGUSR2 => {
+                        eprintln!("There is no elevator 2 to recalibrate.");
+                        continue;
+                    }
+                    SIGHUP => Command::ResetAll,
+                    SIGINT => Command::Stop("SIGINT"),
+                    SIGTERM => Command::Stop("SIGTERM"),
+                    _ => unreachable!(),
+                };
+
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    match create_fifo(COMMAND_FIFO) {
+        Ok(()) => {
+            thread::spawn(move || read_commands(sender, cars));
+        }
+        Err(e) => eprintln!("Couldn't create {}, commands disabled: {}", COMMAND_FIFO, e),
+    }
 
     println!("Elevator is ready.");
     println!("Press Ctrl+C to stop (or send SIGINT/SIGTERM but not SIGKILL).");
+    println!(
+        "Write \"recalibrate <elevator>\" to {} to recalibrate an elevator, 1 to {}.",
+        COMMAND_FIFO, cars
+    );
+    println!(
+        "Write \"reset <elevator>\" to {} or send SIGHUP to return the elevators in emergency to service.",
+        COMMAND_FIFO
+    );
 
-    let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
+    for command in commands {
+        match command {
+            Command::Recalibrate(car) => {
+                println!("Recalibrating elevator {}...", car);
 
-    for signal in signals.forever() {
-        let signal = match signal {
-            SIGINT => "SIGINT",
-            SIGTERM => "SIGTERM",
-            _ => unreachable!(),
-        };
+                if let Err(e) = elevator.recalibrate(car) {
+                    eprintln!("Couldn't recalibrate elevator {}: {}", car, e);
+                }
+            }
+            Command::Reset(car) => {
+                println!("Resetting the emergency of elevator {}...", car);
+
+                if let Err(e) = elevator.reset_emergency(car) {
+                    eprintln!("Couldn't reset elevator {}: {}", car, e);
+                }
+            }
+            Command::ResetAll => {
+                let cars = elevator.in_emergency();
+
+                if cars.is_empty() {
+                    println!("No elevator is in emergency.");
+                }
+
+                for car in cars {
+                    println!("Resetting the emergency of elevator {}...", car);
+
+                    if let Err(e) = elevator.reset_emergency(car) {
+                        eprintln!("Couldn't reset elevator {}: {}", car, e);
+                    }
+                }
+            }
+            Command::Stop(signal) => {
+                println!("Received {}, shutting down...", signal);
 
-        println!("Received {}, shutting down...", signal);
-        elevator.stop();
+                if let Err(e) = elevator.stop() {
+                    eprintln!("Couldn't stop elevator cleanly: {}", e);
+                }
 
-        break;
+                break;
+            }
+        }
     }
 }
@@ -0,0 +1,5 @@
+pub mod plant;
+pub mod rig;
+
+#[cfg(test)]
+mod tests;
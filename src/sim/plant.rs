@@ -0,0 +1,156 @@
+use crate::common::{Direction, Floor};
+use std::time::Duration;
+
+// Integration step, small enough to keep the friction model stable at any caller period
+const SUBSTEP: f64 = 0.001;
+
+// Below this speed the cabin is considered at rest and held by static friction
+const REST_SPEED: f64 = 1e-4;
+
+/// Physical constants of a simulated elevator shaft.
+#[derive(Clone, Debug)]
+pub struct PlantParameters {
+    /// Moving mass of cabin, counterweight and rotor reflected to the cabin (kg)
+    pub mass: f64,
+    /// Force applied on the cabin at 100% duty cycle (N)
+    pub motor_gain: f64,
+    /// Friction proportional to speed (N.s/m)
+    pub viscous_friction: f64,
+    /// Constant friction opposing motion, also the force needed to start moving (N)
+    pub coulomb_friction: f64,
+    /// Damping applied when the H-bridge brakes the motor on Stop (N.s/m)
+    pub brake_damping: f64,
+    /// Encoder ticks per metre of travel
+    pub ticks_per_metre: f64,
+    /// Height of each landing from the bottom of the shaft, from the lowest one (m)
+    pub landings: Vec<f64>,
+    /// Length of the shaft where each floor sensor is active, centered on the landing (m)
+    pub sensor_band: f64,
+    /// Top end stop, the bottom end stop is at 0 (m)
+    pub shaft_height: f64,
+}
+
+impl Default for PlantParameters {
+    // Roughly the lab rig: a full trip takes a few seconds and the encoder spans ~25000 ticks
+    fn default() -> Self {
+        PlantParameters {
+            mass: 300.0,
+            motor_gain: 1200.0,
+            viscous_friction: 1170.0,
+            coulomb_friction: 30.0,
+            brake_damping: 6000.0,
+            ticks_per_metre: 2500.0,
+            landings: vec![0.4, 3.4, 6.4, 9.4],
+            sensor_band: 0.2,
+            shaft_height: 10.0,
+        }
+    }
+}
+
+/// Simulated cabin driven by the same direction and duty cycle given to `EngineControl`.
+pub struct ElevatorPlant {
+    parameters: PlantParameters,
+    position: f64,
+    velocity: f64,
+}
+
+impl ElevatorPlant {
+    pub fn new(parameters: PlantParameters) -> Self {
+        ElevatorPlant {
+            parameters,
+            position: 0.0,
+            velocity: 0.0,
+        }
+    }
+
+    pub fn parameters(&self) -> &PlantParameters {
+        &self.parameters
+    }
+
+    /// Cabin height from the bottom of the shaft (m)
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Cabin speed, positive going up (m/s)
+    pub fn velocity(&self) -> f64 {
+        self.velocity
+    }
+
+    /// Places the cabin at rest at the given height (m)
+    pub fn set_position(&mut self, position: f64) {
+        self.position = position.clamp(0.0, self.parameters.shaft_height);
+        self.velocity = 0.0;
+    }
+
+    /// Count the ESP32 would report for the current position
+    pub fn encoder(&self) -> i32 {
+        (self.position * self.parameters.ticks_per_metre).round() as i32
+    }
+
+    /// Whether the floor sensor of the landing is active, mirrors the `FloorSensors` level
+    pub fn sensor(&self, floor: Floor) -> bool {
+        self.parameters
+            .landings
+            .get(floor.index())
+            .is_some_and(|landing| {
+                (self.position - landing).abs() <= self.parameters.sensor_band / 2.0
+            })
+    }
+
+    /// All floor sensors, from the lowest landing
+    pub fn sensors(&self) -> Vec<bool> {
+        (0..self.parameters.landings.len())
+            .map(|landing| self.sensor(Floor(landing)))
+            .collect()
+    }
+
+    /// Advances the simulation by `elapsed` with the motor driven at `duty_cycle` (0.0 to 1.0)
+    pub fn step(&mut self, elapsed: Duration, direction: Direction, duty_cycle: f64) {
+        let mut remaining = elapsed.as_secs_f64();
+
+        while remaining > 0.0 {
+            let dt = remaining.min(SUBSTEP);
+            self.integrate(dt, direction, duty_cycle.clamp(0.0, 1.0));
+            remaining -= dt;
+        }
+    }
+
+    fn integrate(&mut self, dt: f64, direction: Direction, duty_cycle: f64) {
+        let p = &self.parameters;
+
+        let drive = match direction {
+            Direction::Up => p.motor_gain * duty_cycle,
+            Direction::Down => -p.motor_gain * duty_cycle,
+            Direction::Stop => -p.brake_damping * self.velocity,
+        };
+
+        let force = drive - p.viscous_friction * self.velocity;
+
+        let force = if self.velocity.abs() > REST_SPEED {
+            force - p.coulomb_friction * self.velocity.signum()
+        } else if force.abs() > p.coulomb_friction {
+            force - p.coulomb_friction * force.signum()
+        } else {
+            // Static friction holds the cabin
+            self.velocity = 0.0;
+            return;
+        };
+
+        let velocity = self.velocity + force / p.mass * dt;
+
+        // Friction can stop the cabin but never reverse it within a step
+        self.velocity = if drive.abs() <= p.coulomb_friction && velocity * self.velocity < 0.0 {
+            0.0
+        } else {
+            velocity
+        };
+
+        self.position += self.velocity * dt;
+
+        if self.position <= 0.0 || self.position >= p.shaft_height {
+            self.position = self.position.clamp(0.0, p.shaft_height);
+            self.velocity = 0.0;
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::common::{Direction, Elevator};
+use crate::elevator::building::Building;
+use crate::error::Result;
+use crate::gpio::floor_sensors::FloorSensors;
+use crate::gpio::motor_driver::{MotorDriver, MotorLog, RecordingMotor, SharedMotor};
+use crate::sim::plant::{ElevatorPlant, PlantParameters};
+use crate::uart::emulator::Esp32Emulator;
+use crate::uart::esp32::{Button, Encoder, Esp32};
+use crate::uart::transport::MemoryTransport;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Plant of one car and the command its motor last received, advanced with the wall clock.
+struct Shaft {
+    plant: ElevatorPlant,
+    direction: Direction,
+    duty_cycle: f64,
+    updated: Instant,
+}
+
+impl Shaft {
+    fn advance(&mut self) {
+        let now = Instant::now();
+
+        self.plant
+            .step(now - self.updated, self.direction, self.duty_cycle);
+        self.updated = now;
+    }
+}
+
+type SharedShaft = Arc<Mutex<Shaft>>;
+
+/// Motor of a simulated shaft, also recording its commands.
+struct PlantMotor {
+    shaft: SharedShaft,
+    recorder: RecordingMotor,
+}
+
+impl MotorDriver for PlantMotor {
+    fn set_direction(&mut self, direction: Direction) -> Result<()> {
+        let mut shaft = self.shaft.lock().unwrap();
+
+        shaft.advance();
+        shaft.direction = direction;
+
+        self.recorder.set_direction(direction)
+    }
+
+    fn set_potency(&mut self, duty_cycle: f64) -> Result<()> {
+        let mut shaft = self.shaft.lock().unwrap();
+
+        shaft.advance();
+        shaft.duty_cycle = duty_cycle;
+
+        self.recorder.set_potency(duty_cycle)
+    }
+}
+
+/// Floor sensors of a simulated shaft.
+struct PlantSensors {
+    shaft: SharedShaft,
+}
+
+impl FloorSensors for PlantSensors {
+    fn landings(&self) -> usize {
+        self.shaft.lock().unwrap().plant.parameters().landings.len()
+    }
+
+    fn is_active(&self, landing: usize) -> bool {
+        let mut shaft = self.shaft.lock().unwrap();
+
+        shaft.advance();
+        shaft.plant.sensors()[landing]
+    }
+}
+
+/// Building simulated in real time: one plant per car behind an emulated ESP32 reporting their encoders.
+pub struct Rig {
+    emulator: Arc<Mutex<Esp32Emulator>>,
+    encoders: Vec<Encoder>,
+    shafts: Vec<SharedShaft>,
+    motors: Vec<SharedMotor>,
+    logs: Vec<MotorLog>,
+}
+
+impl Rig {
+    /// Every car of the building in a shaft of the given parameters, its cabin at the bottom
+    pub fn new(building: &Building, parameters: PlantParameters) -> Self {
+        Rig::with_shafts(building, vec![parameters; building.cars.len()])
+    }
+
+    /// Each car of the building in its own shaft, given in the order of the building topology
+    pub fn with_shafts(building: &Building, shafts: Vec<PlantParameters>) -> Self {
+        let blocks = building
+            .cars
+            .iter()
+            .map(|car| {
+                let (first, last) = car.block();
+                first.0..=last.0
+            })
+            .collect();
+        let encoders: Vec<_> = building.cars.iter().map(|car| car.encoder).collect();
+        let emulator = Esp32Emulator::with_button_blocks(blocks).with_encoders(encoders.clone());
+
+        let shafts: Vec<_> = shafts
+            .into_iter()
+            .map(|parameters| {
+                Arc::new(Mutex::new(Shaft {
+                    plant: ElevatorPlant::new(parameters),
+                    direction: Direction::Stop,
+                    duty_cycle: 0.0,
+                    updated: Instant::now(),
+                }))
+            })
+            .collect();
+
+        let recorders: Vec<_> = shafts.iter().map(|_| RecordingMotor::new()).collect();
+        let logs = recorders.iter().map(RecordingMotor::log).collect();
+        let motors = shafts
+            .iter()
+            .zip(recorders)
+            .map(|(shaft, recorder)| {
+                SharedMotor::new(PlantMotor {
+                    shaft: shaft.clone(),
+                    recorder,
+                })
+            })
+            .collect();
+
+        Rig {
+            emulator: Arc::new(Mutex::new(emulator)),
+            encoders,
+            shafts,
+            motors,
+            logs,
+        }
+    }
+
+    /// Link to the emulated board, the encoders follow the cabins on every transaction
+    pub fn esp32(&self) -> Esp32<MemoryTransport> {
+        let emulator = self.emulator.clone();
+        let encoders = self.encoders.clone();
+        let shafts = self.shafts.clone();
+
+        Esp32::with_transport(MemoryTransport::new(move |frame: &[u8]| {
+            let mut emulator = emulator.lock().unwrap();
+
+            for (encoder, shaft) in encoders.iter().zip(&shafts) {
+                let mut shaft = shaft.lock().unwrap();
+
+                shaft.advance();
+                emulator.set_encoder(*encoder, shaft.plant.encoder());
+            }
+
+            emulator.receive(frame)
+        }))
+    }
+
+    /// Motor and floor sensors of every car, in the order of the building topology
+    pub fn cars(&self) -> Vec<(SharedMotor, Box<dyn FloorSensors>)> {
+        self.motors
+            .iter()
+            .zip(&self.shafts)
+            .map(|(motor, shaft)| {
+                let sensors: Box<dyn FloorSensors> = Box::new(PlantSensors {
+                    shaft: shaft.clone(),
+                });
+
+                (motor.clone(), sensors)
+            })
+            .collect()
+    }
+
+    /// Commands received by the motor of the car
+    pub fn log(&self, elevator: Elevator) -> MotorLog {
+        self.logs[elevator.index()].clone()
+    }
+
+    /// Encoder count of the cabin of the car
+    pub fn position(&self, elevator: Elevator) -> i32 {
+        let mut shaft = self.shafts[elevator.index()].lock().unwrap();
+
+        shaft.advance();
+        shaft.plant.encoder()
+    }
+
+    /// Places the cabin of the car at rest at the given height (m)
+    pub fn set_position(&self, elevator: Elevator, position: f64) {
+        let mut shaft = self.shafts[elevator.index()].lock().unwrap();
+
+        shaft.advance();
+        shaft.plant.set_position(position);
+    }
+
+    /// Presses a button of the panel as a passenger would
+    pub fn press(&self, button: Button) {
+        self.emulator.lock().unwrap().set_register(button.0, true);
+    }
+
+    /// Whether the lamp of the button is lit
+    pub fn is_lit(&self, button: Button) -> bool {
+        self.emulator.lock().unwrap().button(button)
+    }
+}
@@ -0,0 +1,93 @@
+use crate::common::{Direction, Floor};
+use crate::sim::plant::{ElevatorPlant, PlantParameters};
+use crate::uart::emulator::Esp32Emulator;
+use crate::uart::esp32::{Encoder, Esp32};
+use crate::uart::transport::MemoryTransport;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn plant_moves_with_direction() {
+    // Arrange
+    let mut plant = ElevatorPlant::new(PlantParameters::default());
+    plant.set_position(5.0);
+    let start = plant.encoder();
+
+    // Act
+    plant.step(Duration::from_secs(1), Direction::Up, 1.0);
+    let after_up = plant.encoder();
+
+    plant.step(Duration::from_secs(2), Direction::Down, 1.0);
+    let after_down = plant.encoder();
+
+    // Assert
+    assert!(after_up > start);
+    assert!(after_down < after_up);
+    assert!(plant.velocity() < 0.0);
+}
+
+#[test]
+fn plant_stops_on_brake_and_friction() {
+    // Arrange
+    let mut plant = ElevatorPlant::new(PlantParameters::default());
+    plant.set_position(5.0);
+
+    // Act
+    plant.step(Duration::from_secs(1), Direction::Up, 1.0);
+    plant.step(Duration::from_secs(1), Direction::Stop, 0.0);
+    let stopped = plant.encoder();
+
+    // A duty cycle weaker than the static friction must not start the cabin
+    plant.step(Duration::from_secs(1), Direction::Up, 0.01);
+
+    // Assert
+    assert_eq!(plant.velocity(), 0.0);
+    assert_eq!(plant.encoder(), stopped);
+}
+
+#[test]
+fn plant_sensors_follow_landings() {
+    // Arrange
+    let mut plant = ElevatorPlant::new(PlantParameters::default());
+    let mut crossed = Vec::new();
+
+    // Act
+    while plant.position() < plant.parameters().shaft_height {
+        plant.step(Duration::from_millis(10), Direction::Up, 0.5);
+
+        for floor in (0..4).map(Floor) {
+            if plant.sensor(floor) && crossed.last() != Some(&floor) {
+                crossed.push(floor);
+            }
+        }
+    }
+
+    // Assert
+    assert_eq!(crossed, vec![Floor(0), Floor(1), Floor(2), Floor(3)]);
+    assert_eq!(plant.sensors(), [false; 4]);
+}
+
+#[test]
+fn plant_feeds_encoder_through_esp32() {
+    // Arrange
+    let mut plant = ElevatorPlant::new(PlantParameters::default());
+    let emulator = Arc::new(Mutex::new(Esp32Emulator::new()));
+
+    let board = emulator.clone();
+    let mut esp32 = Esp32::with_transport(MemoryTransport::new(move |frame: &[u8]| {
+        board.lock().unwrap().receive(frame)
+    }));
+
+    // Act
+    plant.step(Duration::from_secs(2), Direction::Up, 1.0);
+    emulator
+        .lock()
+        .unwrap()
+        .set_encoder(Encoder(0), plant.encoder());
+
+    // Assert
+    assert_eq!(
+        esp32.get_encoder_value(Encoder(0)).unwrap(),
+        plant.encoder()
+    );
+}
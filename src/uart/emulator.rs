@@ -0,0 +1,214 @@
+use crate::common::Elevator;
+use crate::uart::crc;
+use crate::uart::esp32::{Button, Encoder};
+use crate::uart::modbus::{
+    READ_ENCODER, READ_REGISTERS, REGISTER_CODE, SEND_PWM, SEND_TEMP, SOURCE_ADDRESS,
+    TARGET_ADDRESS, WRITE_REGISTERS,
+};
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+
+// Address + code + subcode
+const HEADER_LEN: usize = 3;
+const TRAILER_LEN: usize = REGISTER_CODE.len() + 2;
+
+// Register blocks of the lab firmware, one per car
+const LAB_BUTTON_BLOCKS: [RangeInclusive<u8>; 2] = [0x00..=0x0A, 0xA0..=0xAA];
+
+/// Software model of the ESP32 board, answering modbus frames the same way the firmware does.
+pub struct Esp32Emulator {
+    buffer: Vec<u8>,
+    button_blocks: Vec<RangeInclusive<u8>>,
+    registers: [u8; 256],
+    /// Position of each encoder channel, by its id on the board
+    encoders: HashMap<Encoder, i32>,
+    pwm: HashMap<Encoder, i32>,
+    /// One per car, the car of each register block
+    temperatures: Vec<f32>,
+}
+
+impl Esp32Emulator {
+    pub fn new() -> Self {
+        Esp32Emulator::with_button_blocks(LAB_BUTTON_BLOCKS.to_vec())
+    }
+
+    /// Board flashed for another building, answering only the given register blocks, one per car
+    ///
+    /// The encoder channels are numbered from 0 like on the lab rig, see [`Self::with_encoders`].
+    pub fn with_button_blocks(button_blocks: Vec<RangeInclusive<u8>>) -> Self {
+        let cars = button_blocks.len();
+
+        Esp32Emulator {
+            buffer: Vec::new(),
+            button_blocks,
+            registers: [0; 256],
+            encoders: HashMap::new(),
+            pwm: HashMap::new(),
+            temperatures: vec![0.0; cars],
+        }
+        .with_encoders((0..cars as u8).map(Encoder))
+    }
+
+    /// Answers only the given encoder channels, whatever their ids
+    pub fn with_encoders(mut self, encoders: impl IntoIterator<Item = Encoder>) -> Self {
+        self.encoders = encoders.into_iter().map(|encoder| (encoder, 0)).collect();
+        self.pwm = self.encoders.clone();
+
+        self
+    }
+
+    /// Moves an encoder channel, adding it when the board did not have it
+    pub fn set_encoder(&mut self, encoder: Encoder, value: i32) {
+        self.encoders.insert(encoder, value);
+        self.pwm.entry(encoder).or_insert(0);
+    }
+
+    /// Last control signal received for the encoder, 0 for an unknown channel
+    pub fn pwm(&self, encoder: Encoder) -> i32 {
+        self.pwm.get(&encoder).copied().unwrap_or(0)
+    }
+
+    pub fn temperature(&self, elevator: Elevator) -> f32 {
+        self.temperatures[elevator.index()]
+    }
+
+    pub fn button(&self, button: Button) -> bool {
+        self.registers[button.0 as usize] != 0
+    }
+
+    /// Changes a button register as if it was pressed on the dashboard, returns false for unknown addresses.
+    pub fn set_register(&mut self, address: u8, state: bool) -> bool {
+        if self.block_of(address).is_none() {
+            return false;
+        }
+
+        self.registers[address as usize] = state as u8;
+
+        true
+    }
+
+    /// Feeds bytes received from the serial line, returning the bytes to send back.
+    ///
+    /// Frames may arrive split or glued together, incomplete frames are kept until the rest arrives.
+    /// Frames with a wrong address, register code or CRC are dropped without an answer, like the board does.
+    pub fn receive(&mut self, bytes: &[u8]) -> Vec<u8> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut output = Vec::new();
+
+        while !self.buffer.is_empty() {
+            let frame_len = match self.frame_len() {
+                Some(Ok(frame_len)) => frame_len,
+                Some(Err(())) => {
+                    // Not the start of a frame, resynchronize on the next byte
+                    self.buffer.remove(0);
+                    continue;
+                }
+                None => break,
+            };
+
+            if self.buffer.len() < frame_len {
+                break;
+            }
+
+            let frame: Vec<u8> = self.buffer.drain(..frame_len).collect();
+
+            if let Some(response) = self.answer(&frame) {
+                output.extend(response);
+            }
+        }
+
+        output
+    }
+
+    // None when more bytes are needed to know the length, Err when the buffer does not start a frame
+    fn frame_len(&self) -> Option<Result<usize, ()>> {
+        if self.buffer[0] != TARGET_ADDRESS {
+            return Some(Err(()));
+        }
+
+        let code = *self.buffer.get(1)?;
+
+        let data_len = if code == READ_ENCODER.code {
+            1
+        } else if code == SEND_PWM.code {
+            5
+        } else if code == READ_REGISTERS(0, 0).code {
+            1
+        } else if code == WRITE_REGISTERS(0, 0).code {
+            1 + *self.buffer.get(3)? as usize
+        } else {
+            return Some(Err(()));
+        };
+
+        Some(Ok(HEADER_LEN + data_len + TRAILER_LEN))
+    }
+
+    fn answer(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        let (body, crc) = frame.split_at(frame.len() - 2);
+
+        if u16::from_le_bytes([crc[0], crc[1]]) != crc::hash(body) {
+            return None;
+        }
+
+        let (body, register_code) = body.split_at(body.len() - REGISTER_CODE.len());
+
+        if register_code != REGISTER_CODE {
+            return None;
+        }
+
+        let (code, subcode, data) = (body[1], body[2], &body[HEADER_LEN..]);
+
+        let mut response = vec![SOURCE_ADDRESS, code];
+
+        if code == READ_ENCODER.code && subcode == READ_ENCODER.subcode {
+            let encoder = *self.encoders.get(&Encoder(data[0]))?;
+
+            response.push(subcode);
+            response.extend(encoder.to_le_bytes());
+        } else if code == SEND_PWM.code && subcode == SEND_PWM.subcode {
+            let pwm = self.pwm.get_mut(&Encoder(data[0]))?;
+            *pwm = i32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+
+            response.push(subcode);
+        } else if code == SEND_TEMP.code && subcode == SEND_TEMP.subcode {
+            let temperature = self.temperatures.get_mut(data[0] as usize)?;
+            *temperature = f32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+
+            response.push(subcode);
+        } else if code == READ_REGISTERS(0, 0).code {
+            let range = self.register_range(subcode, data[0])?;
+
+            response.extend(&self.registers[range]);
+        } else if code == WRITE_REGISTERS(0, 0).code {
+            let range = self.register_range(subcode, data[0])?;
+            let values = &data[1..];
+
+            self.registers[range.clone()].copy_from_slice(values);
+            response.extend(&self.registers[range]);
+        } else {
+            return None;
+        }
+
+        response.extend(crc::hash(&response).to_le_bytes());
+
+        Some(response)
+    }
+
+    fn block_of(&self, address: u8) -> Option<&RangeInclusive<u8>> {
+        self.button_blocks
+            .iter()
+            .find(|block| block.contains(&address))
+    }
+
+    fn register_range(&self, start: u8, qtd: u8) -> Option<Range<usize>> {
+        let end = start.checked_add(qtd.checked_sub(1)?)?;
+
+        // A transaction never spans two blocks
+        if !self.block_of(start)?.contains(&end) {
+            return None;
+        }
+
+        Some(start as usize..end as usize + 1)
+    }
+}
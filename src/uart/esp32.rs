@@ -1,416 +1,221 @@
-use crate::common::{Elevator, Floor};
+use crate::common::Elevator;
+use crate::error::{DeviceError, ProtocolError, Result, TransportError};
 use crate::uart::modbus::{
-    create_modbus, read_modbus, READ_ENCODER, READ_REGISTERS, SEND_PWM, SEND_TEMP, WRITE_REGISTERS,
+    create_modbus, read_modbus, ModbusOperation, READ_ENCODER, READ_REGISTERS, SEND_PWM, SEND_TEMP,
+    WRITE_REGISTERS,
 };
-use rppal::uart::{Parity, Queue, Uart};
-use std::{collections::HashMap, time::Duration};
-
-#[derive(Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-pub enum Encoder {
-    One = 0x00,
-    Two = 0x01,
+use crate::uart::transport::Transport;
+use rppal::uart::{Parity, Uart};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    time::{Duration, Instant},
+};
+
+/// Encoder channel of the ESP32, set per car by the building topology.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Encoder(pub u8);
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const ATTEMPTS: u8 = 3;
+
+// Overrides the serial device, e.g. to talk to the emulator pseudo-terminal
+const SERIAL_PORT_VAR: &str = "ESP32_SERIAL_PORT";
+
+/// Button register on the ESP32, its floor and car come from the building topology.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Button(pub u8);
+
+/// Outcome of the latest transactions, used to detect a lost link.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LinkHealth {
+    /// Transactions that failed every attempt since the last one answered
+    pub consecutive_failures: u32,
+    pub last_failure: Option<Instant>,
 }
 
-const BUTTON_COUNT: u8 = 22;
-
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(u8)]
-pub enum Button {
-    GroundFloorUp1 = 0x00,
-    FirstFloorUp1 = 0x01,
-    FirstFloorDown1 = 0x02,
-    SecondFloorUp1 = 0x03,
-    SecondFloorDown1 = 0x04,
-    ThirdFloorDown1 = 0x05,
-    Emergency1 = 0x06,
-    GroundFloorCall1 = 0x07,
-    FirstFloorCall1 = 0x08,
-    SecondFloorCall1 = 0x09,
-    ThirdFloorCall1 = 0x0A,
-
-    GroundFloorUp2 = 0xA0,
-    FirstFloorUp2 = 0xA1,
-    FirstFloorDown2 = 0xA2,
-    SecondFloorUp2 = 0xA3,
-    SecondFloorDown2 = 0xA4,
-    ThirdFloorDown2 = 0xA5,
-    Emergency2 = 0xA6,
-    GroundFloorCall2 = 0xA7,
-    FirstFloorCall2 = 0xA8,
-    SecondFloorCall2 = 0xA9,
-    ThirdFloorCall2 = 0xAA,
+pub struct Esp32<T: Transport = Uart> {
+    transport: T,
+    health: LinkHealth,
 }
 
-impl Button {
-    pub fn into_floor(self, elevator: Elevator) -> Option<Floor> {
-        match elevator {
-            Elevator::One => match self {
-                Button::GroundFloorUp1 => Some(Floor::Ground),
-                Button::FirstFloorUp1 => Some(Floor::First),
-                Button::FirstFloorDown1 => Some(Floor::First),
-                Button::SecondFloorUp1 => Some(Floor::Second),
-                Button::SecondFloorDown1 => Some(Floor::Second),
-                Button::ThirdFloorDown1 => Some(Floor::Third),
-                Button::GroundFloorCall1 => Some(Floor::Ground),
-                Button::FirstFloorCall1 => Some(Floor::First),
-                Button::SecondFloorCall1 => Some(Floor::Second),
-                Button::ThirdFloorCall1 => Some(Floor::Third),
-                _ => None,
-            },
-            Elevator::Two => match self {
-                Button::GroundFloorUp2 => Some(Floor::Ground),
-                Button::FirstFloorUp2 => Some(Floor::First),
-                Button::FirstFloorDown2 => Some(Floor::First),
-                Button::SecondFloorUp2 => Some(Floor::Second),
-                Button::SecondFloorDown2 => Some(Floor::Second),
-                Button::ThirdFloorDown2 => Some(Floor::Third),
-                Button::GroundFloorCall2 => Some(Floor::Ground),
-                Button::FirstFloorCall2 => Some(Floor::First),
-                Button::SecondFloorCall2 => Some(Floor::Second),
-                Button::ThirdFloorCall2 => Some(Floor::Third),
-                _ => None,
-            },
+impl Esp32 {
+    pub fn new() -> Result<Self> {
+        let mut uart = match env::var(SERIAL_PORT_VAR) {
+            Ok(path) => Uart::with_path(path, 115200, Parity::None, 8, 1),
+            Err(_) => Uart::new(115200, Parity::None, 8, 1),
         }
-    }
+        .map_err(DeviceError::Uart)?;
 
-    pub fn get_buttons(elevator: Elevator, floor: Floor) -> Vec<Button> {
-        match elevator {
-            Elevator::One => match floor {
-                Floor::Ground => vec![Button::GroundFloorUp1, Button::GroundFloorCall1],
-                Floor::First => vec![
-                    Button::FirstFloorUp1,
-                    Button::FirstFloorDown1,
-                    Button::FirstFloorCall1,
-                ],
-                Floor::Second => vec![
-                    Button::SecondFloorUp1,
-                    Button::SecondFloorDown1,
-                    Button::SecondFloorCall1,
-                ],
-                Floor::Third => vec![Button::ThirdFloorDown1, Button::ThirdFloorCall1],
-                Floor::Undefined => unreachable!(),
-            },
-            Elevator::Two => match floor {
-                Floor::Ground => vec![Button::GroundFloorUp2, Button::GroundFloorCall2],
-                Floor::First => vec![
-                    Button::FirstFloorUp2,
-                    Button::FirstFloorDown2,
-                    Button::FirstFloorCall2,
-                ],
-                Floor::Second => vec![
-                    Button::SecondFloorUp2,
-                    Button::SecondFloorDown2,
-                    Button::SecondFloorCall2,
-                ],
-                Floor::Third => vec![Button::ThirdFloorDown2, Button::ThirdFloorCall2],
-                Floor::Undefined => unreachable!(),
-            },
-        }
+        uart.set_write_mode(true).map_err(DeviceError::Uart)?;
+        uart.set_read_mode(0, READ_TIMEOUT)
+            .map_err(DeviceError::Uart)?;
+
+        Ok(Esp32::with_transport(uart))
     }
 }
 
-impl From<u8> for Button {
-    fn from(value: u8) -> Self {
-        match value {
-            0x00 => Button::GroundFloorUp1,
-            0x01 => Button::FirstFloorUp1,
-            0x02 => Button::FirstFloorDown1,
-            0x03 => Button::SecondFloorUp1,
-            0x04 => Button::SecondFloorDown1,
-            0x05 => Button::ThirdFloorDown1,
-            0x06 => Button::Emergency1,
-            0x07 => Button::GroundFloorCall1,
-            0x08 => Button::FirstFloorCall1,
-            0x09 => Button::SecondFloorCall1,
-            0x0A => Button::ThirdFloorCall1,
-
-            0xA0 => Button::GroundFloorUp2,
-            0xA1 => Button::FirstFloorUp2,
-            0xA2 => Button::FirstFloorDown2,
-            0xA3 => Button::SecondFloorUp2,
-            0xA4 => Button::SecondFloorDown2,
-            0xA5 => Button::ThirdFloorDown2,
-            0xA6 => Button::Emergency2,
-            0xA7 => Button::GroundFloorCall2,
-            0xA8 => Button::FirstFloorCall2,
-            0xA9 => Button::SecondFloorCall2,
-            0xAA => Button::ThirdFloorCall2,
-            _ => panic!("Invalid button value: {:X}", value),
+impl<T: Transport> Esp32<T> {
+    pub fn with_transport(transport: T) -> Self {
+        Esp32 {
+            transport,
+            health: LinkHealth::default(),
         }
     }
-}
-
-pub struct Esp32 {
-    uart: Uart,
-}
-
-impl Esp32 {
-    pub fn new() -> Self {
-        let mut uart = Uart::new(115200, Parity::None, 8, 1).unwrap();
 
-        uart.set_write_mode(true).unwrap();
-        uart.set_read_mode(0, Duration::from_millis(100)).unwrap();
-
-        Esp32 { uart }
+    pub fn transport(&self) -> &T {
+        &self.transport
     }
 
-    fn set_read_size(&mut self, size: usize) {
-        self.uart
-            .set_read_mode(size as u8, Duration::from_millis(100))
-            .unwrap();
+    pub fn link_health(&self) -> LinkHealth {
+        self.health
     }
 
-    pub fn get_encoder_value(&mut self, encoder: Encoder) -> i32 {
-        let mut success = false;
-        let mut encoder_value = -1;
+    fn exchange(&mut self, request: &[u8], response: &mut [u8]) -> Result<()> {
+        // Drop leftovers of earlier answers so they are not taken as this response
+        self.transport.flush()?;
 
-        let request = create_modbus(READ_ENCODER, &[encoder as u8]);
+        let wrote = self.transport.write(request)?;
 
-        let mut response = [0; 9];
-        self.set_read_size(response.len());
+        if wrote != request.len() {
+            return Err(TransportError::ShortWrite {
+                expected: request.len(),
+                wrote,
+            }
+            .into());
+        }
 
-        for current_try in 1..=3 {
-            let wrote = self.uart.write(&request).unwrap();
-            let read = self.uart.read(&mut response).unwrap();
+        let read = self.transport.read(response, READ_TIMEOUT)?;
 
-            if wrote != request.len() || read != response.len() {
-                eprintln!("({}) Fail to read/write uart:", current_try);
-                self.uart.flush(Queue::Both).unwrap();
-                continue;
+        if read != response.len() {
+            return Err(TransportError::ShortRead {
+                expected: response.len(),
+                read,
             }
+            .into());
+        }
 
-            let response = read_modbus(READ_ENCODER, &response);
-
-            match response {
-                Ok(value) => {
-                    success = true;
-                    encoder_value = i32::from_le_bytes([value[0], value[1], value[2], value[3]]);
-                    break;
-                }
-                Err(msg) => {
-                    eprintln!("({}) Couldn't get encoder: {}", current_try, msg);
-                    self.uart.flush(Queue::Both).unwrap();
-                    continue;
-                }
-            };
+        Ok(())
+    }
+
+    // Sends the request until a valid answer arrives, returning the data of the last attempt
+    fn transaction(
+        &mut self,
+        operation: ModbusOperation,
+        data: &[u8],
+        response_len: usize,
+        action: &str,
+    ) -> Result<Vec<u8>> {
+        let request = create_modbus(operation, data);
+        let mut response = vec![0; response_len];
+
+        let mut result = Ok(Vec::new());
+
+        for current_try in 1..=ATTEMPTS {
+            result = self
+                .exchange(&request, &mut response)
+                .and_then(|_| Ok(read_modbus(operation, &response)?.to_vec()));
+
+            match &result {
+                Ok(_) => break,
+                Err(e) => eprintln!("({}) Couldn't {}: {}", current_try, action, e),
+            }
         }
 
-        if !success {
-            panic!("Couldn't get encoder after 3 attempts");
+        if result.is_ok() {
+            self.health.consecutive_failures = 0;
+        } else {
+            self.health.consecutive_failures += 1;
+            self.health.last_failure = Some(Instant::now());
         }
 
-        encoder_value
+        result
     }
 
-    pub fn send_control_signal(&mut self, encoder: Encoder, pwm: i32) {
-        let mut success = false;
+    pub fn get_encoder_value(&mut self, encoder: Encoder) -> Result<i32> {
+        let value = self.transaction(READ_ENCODER, &[encoder.0], 9, "get encoder")?;
 
+        Ok(i32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+    }
+
+    pub fn send_control_signal(&mut self, encoder: Encoder, pwm: i32) -> Result<()> {
         let mut data = Vec::with_capacity(5);
-        data.push(encoder as u8);
+        data.push(encoder.0);
         data.extend(&pwm.to_le_bytes());
-        let request = create_modbus(SEND_PWM, &data);
-
-        let mut response = [0; 5];
-        self.set_read_size(response.len());
 
-        for current_try in 1..=3 {
-            let wrote = self.uart.write(&request).unwrap();
-            let read = self.uart.read(&mut response).unwrap();
-            self.uart.read(&mut response).unwrap();
-
-            if wrote != request.len() || read != response.len() {
-                eprintln!("({}) Fail to read/write uart:", current_try);
-                self.uart.flush(Queue::Both).unwrap();
-                continue;
-            }
+        self.transaction(SEND_PWM, &data, 5, "send control signal")?;
 
-            let response = read_modbus(SEND_PWM, &response);
-
-            match response {
-                Ok(_) => {
-                    success = true;
-                    break;
-                }
-                Err(msg) => {
-                    eprintln!("({}) Couldn't send control signal: {}", current_try, msg);
-                    self.uart.flush(Queue::Both).unwrap();
-                    continue;
-                }
-            };
-        }
-
-        if !success {
-            panic!("Failed to send PWM after 3 attempts");
-        }
+        Ok(())
     }
 
-    pub fn send_temp(&mut self, elevator: Elevator, temp: f32) {
-        let mut success = false;
-
+    pub fn send_temp(&mut self, elevator: Elevator, temp: f32) -> Result<()> {
         let mut data = Vec::with_capacity(5);
-        data.push(elevator as u8);
+        data.push(elevator.index() as u8);
         data.extend(&temp.to_le_bytes());
-        let request = create_modbus(SEND_TEMP, &data);
 
-        let mut response = [0; 5];
-        self.set_read_size(response.len());
+        self.transaction(SEND_TEMP, &data, 5, "send temp")?;
 
-        for current_try in 1..=3 {
-            let wrote = self.uart.write(&request).unwrap();
-            let read = self.uart.read(&mut response).unwrap();
-            self.uart.read(&mut response).unwrap();
+        Ok(())
+    }
 
-            if wrote != request.len() || read != response.len() {
-                eprintln!("({}) Fail to read/write uart:", current_try);
-                self.uart.flush(Queue::Both).unwrap();
-                continue;
+    fn check_range(start: Button, end: Button) -> Result<(u8, u8)> {
+        if end < start {
+            return Err(ProtocolError::ButtonRange {
+                start: start.0,
+                end: end.0,
             }
-
-            let response = read_modbus(SEND_TEMP, &response);
-
-            match response {
-                Ok(_) => {
-                    success = true;
-                    break;
-                }
-                Err(msg) => {
-                    eprintln!("({}) Couldn't send temp: {}", current_try, msg);
-                    self.uart.flush(Queue::Both).unwrap();
-                    continue;
-                }
-            };
+            .into());
         }
 
-        if !success {
-            panic!("Failed to send temperature after 3 attempts");
-        }
+        Ok((start.0, end.0))
     }
 
+    /// Registers `start` to `end` inclusive, usually the block of one car
     pub fn read_buttons_in_range(
         &mut self,
-        elevator: Elevator,
         start: Button,
         end: Button,
-    ) -> HashMap<Button, bool> {
-        let mut success = false;
-        let mut buttons = HashMap::with_capacity(BUTTON_COUNT as usize / 2);
-
-        let start_idx = start as u8;
-        let end_idx = end as u8;
-
-        let (min, max) = if elevator == Elevator::One {
-            (0x00, 0x0A)
-        } else {
-            (0xA0, 0xAA)
-        };
-
-        if end_idx < start_idx {
-            panic!("Invalid button range: {:X} - {:X}", start_idx, end_idx);
-        }
-
-        if start_idx < min || start_idx > max {
-            panic!("Invalid start button: {:X}", start_idx);
-        }
-
-        if end_idx < min || end_idx > max {
-            panic!("Invalid end button: {:X}", end_idx);
-        }
+    ) -> Result<HashMap<Button, bool>> {
+        let (start_idx, end_idx) = Self::check_range(start, end)?;
 
         let data_len = end_idx - start_idx + 1;
-
         let operation = READ_REGISTERS(start_idx, data_len);
-        let request = create_modbus(operation, &[data_len]);
 
-        let mut response = vec![0; 4 + data_len as usize];
-        self.set_read_size(response.len());
+        let value = self.transaction(
+            operation,
+            &[data_len],
+            4 + data_len as usize,
+            "read buttons",
+        )?;
 
-        for current_try in 1..=3 {
-            let wrote = self.uart.write(&request).unwrap();
-            let read = self.uart.read(&mut response).unwrap();
-
-            if wrote != request.len() || read != response.len() {
-                eprintln!("({}) Fail to read/write uart:", current_try);
-                self.uart.flush(Queue::Both).unwrap();
-                continue;
-            }
+        let mut buttons = HashMap::with_capacity(data_len as usize);
 
-            let response = read_modbus(operation, &response);
-
-            match response {
-                Ok(value) => {
-                    success = true;
-
-                    for i in start_idx..=end_idx {
-                        buttons.insert(Button::from(i), value[(i - start_idx) as usize] != 0);
-                    }
-
-                    break;
-                }
-                Err(msg) => {
-                    eprintln!("({}) Couldn't read buttons: {}", current_try, msg);
-                    self.uart.flush(Queue::Both).unwrap();
-                    continue;
-                }
-            };
-        }
-
-        if !success {
-            panic!("Failed to read buttons after 3 attempts");
+        for i in start_idx..=end_idx {
+            buttons.insert(Button(i), value[(i - start_idx) as usize] != 0);
         }
 
-        buttons
-    }
-
-    pub fn read_all_buttons(&mut self, elevator: Elevator) -> HashMap<Button, bool> {
-        match elevator {
-            Elevator::One => self.read_buttons_in_range(
-                elevator,
-                Button::GroundFloorUp1,
-                Button::ThirdFloorCall1,
-            ),
-            Elevator::Two => self.read_buttons_in_range(
-                elevator,
-                Button::GroundFloorUp2,
-                Button::ThirdFloorCall2,
-            ),
-        }
+        Ok(buttons)
     }
 
     pub fn write_button_in_range(
         &mut self,
-        elevator: Elevator,
         start: Button,
         end: Button,
         state: &[bool],
-    ) {
-        let mut success = false;
-
-        let (min, max) = if elevator == Elevator::One {
-            (0x00, 0x0A)
-        } else {
-            (0xA0, 0xAA)
-        };
-
-        let start_idx = start as u8;
-        let end_idx = end as u8;
-
-        if end_idx < start_idx {
-            panic!("Invalid button range: {:X} - {:X}", start_idx, end_idx);
-        }
-
-        if start_idx < min || start_idx > max {
-            panic!("Invalid start button: {:X}", start_idx);
-        }
-
-        if end_idx < min || end_idx > max {
-            panic!("Invalid end button: {:X}", end_idx);
-        }
+    ) -> Result<()> {
+        let (start_idx, end_idx) = Self::check_range(start, end)?;
 
         let data_len = end_idx - start_idx + 1;
 
         if state.len() != data_len as usize {
-            panic!("Invalid state length: {}", state.len());
+            return Err(ProtocolError::StateLength {
+                expected: data_len as usize,
+                actual: state.len(),
+            }
+            .into());
         }
 
         let mut data = Vec::with_capacity(1 + data_len as usize);
@@ -422,59 +227,13 @@ impl Esp32 {
         }
 
         let operation = WRITE_REGISTERS(start_idx, data_len);
-        let request = create_modbus(operation, &data);
 
-        let mut response = vec![0; 4 + data_len as usize];
-        self.set_read_size(response.len());
+        self.transaction(operation, &data, 4 + data_len as usize, "write buttons")?;
 
-        for current_try in 1..=3 {
-            let wrote = self.uart.write(&request).unwrap();
-            let read = self.uart.read(&mut response).unwrap();
-
-            if wrote != request.len() || read != response.len() {
-                eprintln!("({}) Fail to read/write uart:", current_try);
-                self.uart.flush(Queue::Both).unwrap();
-                continue;
-            }
-
-            let response = read_modbus(operation, &response);
-
-            match response {
-                Ok(_) => {
-                    success = true;
-                    break;
-                }
-                Err(msg) => {
-                    eprintln!("({}) Couldn't write buttons: {}", current_try, msg);
-                    self.uart.flush(Queue::Both).unwrap();
-                    continue;
-                }
-            };
-        }
-
-        if !success {
-            panic!("Failed to write buttons after 3 attempts");
-        }
-    }
-
-    pub fn write_button(&mut self, elevator: Elevator, button: Button, state: bool) {
-        self.write_button_in_range(elevator, button, button, &[state]);
+        Ok(())
     }
 
-    pub fn write_all_buttons(&mut self, elevator: Elevator, state: &[bool]) {
-        match elevator {
-            Elevator::One => self.write_button_in_range(
-                elevator,
-                Button::GroundFloorUp1,
-                Button::ThirdFloorCall1,
-                state,
-            ),
-            Elevator::Two => self.write_button_in_range(
-                elevator,
-                Button::GroundFloorUp2,
-                Button::ThirdFloorCall2,
-                state,
-            ),
-        }
+    pub fn write_button(&mut self, button: Button, state: bool) -> Result<()> {
+        self.write_button_in_range(button, button, &[state])
     }
 }
@@ -1,6 +1,8 @@
+pub mod emulator;
 pub mod esp32;
+pub mod transport;
 
-mod crc;
+pub(crate) mod crc;
 mod modbus;
 
 #[cfg(test)]
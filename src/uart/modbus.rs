@@ -1,14 +1,15 @@
+use crate::error::ProtocolError;
 use crate::uart::crc;
 
-const SOURCE_ADDRESS: u8 = 0x00;
-const TARGET_ADDRESS: u8 = 0x01;
-const REGISTER_CODE: [u8; 4] = [6, 5, 2, 1];
+pub const SOURCE_ADDRESS: u8 = 0x00;
+pub const TARGET_ADDRESS: u8 = 0x01;
+pub const REGISTER_CODE: [u8; 4] = [6, 5, 2, 1];
 
 #[derive(Clone, Copy)]
 pub struct ModbusOperation {
-    code: u8,
-    subcode: u8,
-    qtd: Option<u8>,
+    pub code: u8,
+    pub subcode: u8,
+    pub qtd: Option<u8>,
 }
 
 pub const READ_ENCODER: ModbusOperation = ModbusOperation {
@@ -77,48 +78,52 @@ pub fn create_modbus(operation: ModbusOperation, data: &[u8]) -> Vec<u8> {
     buffer
 }
 
-pub fn read_modbus(operation: ModbusOperation, buffer: &[u8]) -> Result<&[u8], String> {
+pub fn read_modbus(operation: ModbusOperation, buffer: &[u8]) -> Result<&[u8], ProtocolError> {
     if buffer.len() < 5 {
-        return Err(format!("Invalid length: {} < 5", buffer.len()));
+        return Err(ProtocolError::Length {
+            minimum: 5,
+            actual: buffer.len(),
+        });
     }
 
     // Every response must start with the source address
     if buffer[0] != SOURCE_ADDRESS {
-        return Err(format!(
-            "Invalid address: {:X} != {:X}",
-            buffer[0], SOURCE_ADDRESS
-        ));
+        return Err(ProtocolError::Address {
+            expected: SOURCE_ADDRESS,
+            actual: buffer[0],
+        });
     }
 
     // Every response must have the same code as the request
     if buffer[1] != operation.code {
-        return Err(format!(
-            "Invalid code: {:X} != {:X}",
-            buffer[1], operation.code
-        ));
+        return Err(ProtocolError::Code {
+            expected: operation.code,
+            actual: buffer[1],
+        });
     }
 
     // If the request does not have a quantity, the next byte must be the subcode
-    let data;
-
-    if operation.qtd.is_none() {
+    let data = if operation.qtd.is_none() {
         if buffer[2] != operation.subcode {
-            return Err(format!(
-                "Invalid subcode: {:X} != {:X}",
-                buffer[2], operation.subcode
-            ));
+            return Err(ProtocolError::Subcode {
+                expected: operation.subcode,
+                actual: buffer[2],
+            });
         }
 
         // The data is the remaining bytes - 2 (CRC16)
-        data = &buffer[3..buffer.len() - 2];
+        &buffer[3..buffer.len() - 2]
     } else {
-        data = &buffer[2..buffer.len() - 2];
-    }
+        &buffer[2..buffer.len() - 2]
+    };
 
     // If the request has a quantity, the data length must match
     if let Some(qtd) = operation.qtd {
         if data.len() != qtd as usize {
-            return Err(format!("Invalid data length: {} != {}", data.len(), qtd));
+            return Err(ProtocolError::DataLength {
+                expected: qtd as usize,
+                actual: data.len(),
+            });
         }
     }
 
@@ -127,7 +132,10 @@ pub fn read_modbus(operation: ModbusOperation, buffer: &[u8]) -> Result<&[u8], S
     let expected_crc = crc::hash(&buffer[..buffer.len() - 2]);
 
     if crc != expected_crc {
-        return Err(format!("Invalid CRC16: {:X} != {:X}", crc, expected_crc));
+        return Err(ProtocolError::Crc {
+            expected: expected_crc,
+            actual: crc,
+        });
     }
 
     Ok(data)
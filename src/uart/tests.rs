@@ -1,56 +1,122 @@
+// The button tests compare each register with the state they expect, true and false alike
+#![allow(clippy::bool_assert_comparison)]
+
 use crate::common::Elevator;
+use crate::elevator::building::Building;
+use crate::uart::emulator::Esp32Emulator;
 use crate::uart::esp32::{Button, Encoder, Esp32};
+use crate::uart::modbus::{create_modbus, read_modbus, READ_ENCODER, SEND_PWM};
+use crate::uart::transport::MemoryTransport;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+
+const ENCODER_1: i32 = 1500;
+const ENCODER_2: i32 = 24000;
+
+// Registers of the lab rig
+const GROUND_UP_1: Button = Button(0x00);
+const EMERGENCY_1: Button = Button(0x06);
+const GROUND_CALL_1: Button = Button(0x07);
+const GROUND_UP_2: Button = Button(0xA0);
+const EMERGENCY_2: Button = Button(0xA6);
+const GROUND_CALL_2: Button = Button(0xA7);
+
+fn fake_board() -> impl FnMut(&[u8]) -> Vec<u8> + Send {
+    let mut emulator = Esp32Emulator::new();
+
+    emulator.set_encoder(Encoder(0), ENCODER_1);
+    emulator.set_encoder(Encoder(1), ENCODER_2);
+
+    move |frame| emulator.receive(frame)
+}
+
+fn connect() -> Esp32<MemoryTransport> {
+    Esp32::with_transport(MemoryTransport::new(fake_board()))
+}
+
+/// Link to an emulated board the test can still inspect
+fn connect_shared() -> (Esp32<MemoryTransport>, Arc<Mutex<Esp32Emulator>>) {
+    let emulator = Arc::new(Mutex::new(Esp32Emulator::new()));
+    let board = Arc::clone(&emulator);
+    let uart = Esp32::with_transport(MemoryTransport::new(move |frame: &[u8]| {
+        board.lock().unwrap().receive(frame)
+    }));
+
+    (uart, emulator)
+}
+
+fn read_block(uart: &mut Esp32<MemoryTransport>, elevator: Elevator) -> HashMap<Button, bool> {
+    let (first, last) = Building::default().car(elevator).block();
+
+    uart.read_buttons_in_range(first, last).unwrap()
+}
+
+fn write_block(uart: &mut Esp32<MemoryTransport>, elevator: Elevator, state: &[bool]) {
+    let (first, last) = Building::default().car(elevator).block();
+
+    uart.write_button_in_range(first, last, state).unwrap()
+}
 
 #[test]
 fn get_encoder_value() {
     // Arrange
-    let mut uart = Esp32::new();
+    let mut uart = connect();
 
     // Act
-    uart.get_encoder_value(Encoder::One);
-    uart.get_encoder_value(Encoder::Two);
+    let value_1 = uart.get_encoder_value(Encoder(0)).unwrap();
+    let value_2 = uart.get_encoder_value(Encoder(1)).unwrap();
 
     // Assert
-    // No assertion needed, just testing that the function does not panic
+    assert_eq!(value_1, ENCODER_1);
+    assert_eq!(value_2, ENCODER_2);
 }
 
 #[test]
 fn send_temp() {
     // Arrange
-    let mut uart = Esp32::new();
+    let (mut uart, emulator) = connect_shared();
 
     // Act
-    uart.send_temp(Elevator::One, 37.0);
-    uart.send_temp(Elevator::Two, 35.0);
+    uart.send_temp(Elevator(0), 37.0).unwrap();
+    let first = emulator.lock().unwrap().temperature(Elevator(0));
+    uart.send_temp(Elevator(1), 35.0).unwrap();
 
     // Assert
-    // No assertion needed, just testing that the function does not panic
+    let emulator = emulator.lock().unwrap();
+    assert_eq!(first, 37.0);
+    assert_eq!(emulator.temperature(Elevator(0)), 37.0);
+    assert_eq!(emulator.temperature(Elevator(1)), 35.0);
 }
 
 #[test]
 fn send_control_signal() {
     // Arrange
-    let mut uart = Esp32::new();
+    let (mut uart, emulator) = connect_shared();
 
     // Act
-    uart.send_control_signal(Encoder::One, 50);
-    uart.send_control_signal(Encoder::Two, 40);
+    uart.send_control_signal(Encoder(0), 50).unwrap();
+    let first = emulator.lock().unwrap().pwm(Encoder(0));
+    uart.send_control_signal(Encoder(1), -40).unwrap();
 
     // Assert
-    // No assertion needed, just testing that the function does not panic
+    let emulator = emulator.lock().unwrap();
+    assert_eq!(first, 50);
+    assert_eq!(emulator.pwm(Encoder(0)), 50);
+    assert_eq!(emulator.pwm(Encoder(1)), -40);
 }
 
 #[test]
 fn read_all_buttons() {
     // Arrange
-    let mut uart = Esp32::new();
+    let mut uart = connect();
 
-    uart.write_all_buttons(Elevator::One, &[false; 11]);
-    uart.write_all_buttons(Elevator::Two, &[false; 11]);
+    write_block(&mut uart, Elevator(0), &[false; 11]);
+    write_block(&mut uart, Elevator(1), &[false; 11]);
 
     // Act
-    let buttons = uart.read_all_buttons(Elevator::One);
-    let buttons2 = uart.read_all_buttons(Elevator::Two);
+    let buttons = read_block(&mut uart, Elevator(0));
+    let buttons2 = read_block(&mut uart, Elevator(1));
 
     // Assert
     assert_eq!(buttons.len(), 11);
@@ -68,17 +134,19 @@ fn read_all_buttons() {
 #[test]
 fn read_buttons_in_range() {
     // Arrange
-    let mut uart = Esp32::new();
+    let mut uart = connect();
 
-    uart.write_all_buttons(Elevator::One, &[false; 11]);
-    uart.write_all_buttons(Elevator::Two, &[false; 11]);
+    write_block(&mut uart, Elevator(0), &[false; 11]);
+    write_block(&mut uart, Elevator(1), &[false; 11]);
 
     // Act
-    let buttons =
-        uart.read_buttons_in_range(Elevator::One, Button::GroundFloorUp1, Button::Emergency1);
+    let buttons = uart
+        .read_buttons_in_range(GROUND_UP_1, EMERGENCY_1)
+        .unwrap();
 
-    let buttons2 =
-        uart.read_buttons_in_range(Elevator::Two, Button::GroundFloorUp2, Button::Emergency2);
+    let buttons2 = uart
+        .read_buttons_in_range(GROUND_UP_2, EMERGENCY_2)
+        .unwrap();
 
     // Assert
     assert_eq!(buttons.len(), 7);
@@ -96,27 +164,23 @@ fn read_buttons_in_range() {
 #[test]
 fn write_button() {
     // Arrange
-    let mut uart = Esp32::new();
+    let mut uart = connect();
 
-    uart.write_all_buttons(Elevator::One, &[false; 11]);
-    uart.write_all_buttons(Elevator::Two, &[false; 11]);
+    write_block(&mut uart, Elevator(0), &[false; 11]);
+    write_block(&mut uart, Elevator(1), &[false; 11]);
 
     // Act
-    uart.write_button(Elevator::One, Button::GroundFloorCall1, true);
-    uart.write_button(Elevator::Two, Button::GroundFloorCall2, true);
+    uart.write_button(GROUND_CALL_1, true).unwrap();
+    uart.write_button(GROUND_CALL_2, true).unwrap();
 
     // Assert
-    let button_state = uart.read_buttons_in_range(
-        Elevator::One,
-        Button::GroundFloorCall1,
-        Button::GroundFloorCall1,
-    )[&Button::GroundFloorCall1];
-
-    let button_state2 = uart.read_buttons_in_range(
-        Elevator::Two,
-        Button::GroundFloorCall2,
-        Button::GroundFloorCall2,
-    )[&Button::GroundFloorCall2];
+    let button_state = uart
+        .read_buttons_in_range(GROUND_CALL_1, GROUND_CALL_1)
+        .unwrap()[&GROUND_CALL_1];
+
+    let button_state2 = uart
+        .read_buttons_in_range(GROUND_CALL_2, GROUND_CALL_2)
+        .unwrap()[&GROUND_CALL_2];
 
     assert_eq!(button_state, true);
     assert_eq!(button_state2, true);
@@ -125,49 +189,37 @@ fn write_button() {
 #[test]
 fn write_button_in_range() {
     // Arrange
-    let mut uart = Esp32::new();
+    let mut uart = connect();
 
-    uart.write_all_buttons(Elevator::One, &[false; 11]);
-    uart.write_all_buttons(Elevator::Two, &[false; 11]);
+    write_block(&mut uart, Elevator(0), &[false; 11]);
+    write_block(&mut uart, Elevator(1), &[false; 11]);
 
     // Act
-    uart.write_button_in_range(
-        Elevator::One,
-        Button::GroundFloorUp1,
-        Button::Emergency1,
-        &[true; 7],
-    );
+    uart.write_button_in_range(GROUND_UP_1, EMERGENCY_1, &[true; 7])
+        .unwrap();
 
-    uart.write_button_in_range(
-        Elevator::Two,
-        Button::GroundFloorUp2,
-        Button::Emergency2,
-        &[true; 7],
-    );
+    uart.write_button_in_range(GROUND_UP_2, EMERGENCY_2, &[true; 7])
+        .unwrap();
 
     // Assert
-    let buttons = uart.read_buttons_in_range(
-        Elevator::One,
-        Button::GroundFloorUp1,
-        Button::GroundFloorCall1,
-    );
+    let buttons = uart
+        .read_buttons_in_range(GROUND_UP_1, GROUND_CALL_1)
+        .unwrap();
 
     for (button, state) in buttons {
-        if button == Button::GroundFloorCall1 {
+        if button == GROUND_CALL_1 {
             assert_eq!(state, false);
         } else {
             assert_eq!(state, true);
         }
     }
 
-    let buttons2 = uart.read_buttons_in_range(
-        Elevator::Two,
-        Button::GroundFloorUp2,
-        Button::GroundFloorCall2,
-    );
+    let buttons2 = uart
+        .read_buttons_in_range(GROUND_UP_2, GROUND_CALL_2)
+        .unwrap();
 
     for (button, state) in buttons2 {
-        if button == Button::GroundFloorCall2 {
+        if button == GROUND_CALL_2 {
             assert_eq!(state, false);
         } else {
             assert_eq!(state, true);
@@ -178,22 +230,170 @@ fn write_button_in_range() {
 #[test]
 fn write_all_buttons() {
     // Arrange
-    let mut uart = Esp32::new();
+    let mut uart = connect();
 
     // Act
-    uart.write_all_buttons(Elevator::One, &[true; 11]);
-    uart.write_all_buttons(Elevator::Two, &[true; 11]);
+    write_block(&mut uart, Elevator(0), &[true; 11]);
+    write_block(&mut uart, Elevator(1), &[true; 11]);
 
     // Assert
-    let buttons = uart.read_all_buttons(Elevator::One);
+    let buttons = read_block(&mut uart, Elevator(0));
 
     for (_, state) in buttons {
         assert_eq!(state, true);
     }
 
-    let buttons2 = uart.read_all_buttons(Elevator::Two);
+    let buttons2 = read_block(&mut uart, Elevator(1));
 
     for (_, state) in buttons2 {
         assert_eq!(state, true);
     }
 }
+
+#[test]
+fn retry_after_corrupted_response() {
+    // Arrange
+    let mut board = fake_board();
+    let mut corrupted = false;
+
+    let mut uart = Esp32::with_transport(MemoryTransport::new(move |frame: &[u8]| {
+        let mut response = board(frame);
+
+        if !corrupted {
+            corrupted = true;
+            response[3] ^= 0xFF;
+        }
+
+        response
+    }));
+
+    // Act
+    let value = uart.get_encoder_value(Encoder(0)).unwrap();
+
+    // Assert
+    assert_eq!(value, ENCODER_1);
+    assert_eq!(uart.transport().written().len(), 2);
+}
+
+#[test]
+fn link_health_counts_failed_transactions() {
+    // Arrange
+    let mut board = fake_board();
+    let silent = Arc::new(AtomicBool::new(true));
+
+    let line = silent.clone();
+    let mut uart = Esp32::with_transport(MemoryTransport::new(move |frame: &[u8]| {
+        let response = board(frame);
+
+        if line.load(Relaxed) {
+            Vec::new()
+        } else {
+            response
+        }
+    }));
+
+    // Act
+    let encoder = uart.get_encoder_value(Encoder(0));
+    let temp = uart.send_temp(Elevator(0), 30.0);
+    let failing = uart.link_health();
+
+    silent.store(false, Relaxed);
+    uart.get_encoder_value(Encoder(0)).unwrap();
+    let restored = uart.link_health();
+
+    // Assert
+    assert!(encoder.is_err());
+    assert!(temp.is_err());
+    assert_eq!(failing.consecutive_failures, 2);
+    assert_eq!(restored.consecutive_failures, 0);
+    assert_eq!(restored.last_failure, failing.last_failure);
+}
+
+#[test]
+fn emulator_split_frames() {
+    // Arrange
+    let mut emulator = Esp32Emulator::new();
+    emulator.set_encoder(Encoder(1), ENCODER_2);
+
+    let mut request = create_modbus(SEND_PWM, &[0, 42, 0, 0, 0]);
+    request.extend(create_modbus(READ_ENCODER, &[1]));
+
+    // Act
+    let first = emulator.receive(&request[..7]);
+    let second = emulator.receive(&request[7..]);
+
+    // Assert
+    assert!(first.is_empty());
+    assert_eq!(emulator.pwm(Encoder(0)), 42);
+    assert_eq!(second.len(), 5 + 9);
+
+    let value = read_modbus(READ_ENCODER, &second[5..]).unwrap();
+    assert_eq!(
+        i32::from_le_bytes([value[0], value[1], value[2], value[3]]),
+        ENCODER_2
+    );
+}
+
+#[test]
+fn emulator_drops_invalid_frames() {
+    // Arrange
+    let mut emulator = Esp32Emulator::new();
+
+    let mut corrupted = create_modbus(READ_ENCODER, &[0]);
+    corrupted[3] ^= 0xFF;
+
+    let mut request = vec![0xFF, 0x42];
+    request.extend(corrupted);
+    request.extend(create_modbus(READ_ENCODER, &[0]));
+
+    // Act
+    let response = emulator.receive(&request);
+
+    // Assert
+    assert_eq!(response.len(), 9);
+    assert!(read_modbus(READ_ENCODER, &response).is_ok());
+}
+
+#[test]
+fn emulator_serves_building_blocks() {
+    // Arrange
+    let mut board = Esp32Emulator::with_button_blocks(vec![0x00..=0x12, 0xA0..=0xB2]);
+    let mut uart = Esp32::with_transport(MemoryTransport::new(move |frame: &[u8]| {
+        board.receive(frame)
+    }));
+
+    // Act
+    uart.write_button(Button(0x11), true).unwrap();
+    let block = uart
+        .read_buttons_in_range(Button(0x00), Button(0x12))
+        .unwrap();
+    let across = uart.read_buttons_in_range(Button(0x12), Button(0xA0));
+    let reversed = uart.read_buttons_in_range(Button(0x12), Button(0x00));
+
+    // Assert
+    assert_eq!(block.len(), 19);
+    assert!(block[&Button(0x11)]);
+    assert!(across.is_err());
+    assert!(reversed.is_err());
+}
+
+#[test]
+fn emulator_keys_encoders_by_id() {
+    // Arrange
+    let mut board = Esp32Emulator::new().with_encoders([Encoder(3), Encoder(7)]);
+    board.set_encoder(Encoder(7), ENCODER_2);
+
+    let mut uart = Esp32::with_transport(MemoryTransport::new(move |frame: &[u8]| {
+        board.receive(frame)
+    }));
+
+    // Act
+    let sparse = uart.get_encoder_value(Encoder(7));
+    let other = uart.get_encoder_value(Encoder(3));
+    let missing = uart.get_encoder_value(Encoder(0));
+
+    // Assert
+    assert_eq!(sparse.unwrap(), ENCODER_2);
+    assert_eq!(other.unwrap(), 0);
+    assert!(missing.is_err());
+}
@@ -0,0 +1,92 @@
+use crate::error::TransportError;
+use rppal::uart::{Queue, Uart};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Byte level link used by [`Esp32`](crate::uart::esp32::Esp32) to exchange modbus frames.
+pub trait Transport: Send {
+    /// Writes a whole frame, returning how many bytes were sent.
+    fn write(&mut self, frame: &[u8]) -> Result<usize, TransportError>;
+
+    /// Waits up to `timeout` for `buffer.len()` bytes, returning how many bytes were read.
+    fn read(&mut self, buffer: &mut [u8], timeout: Duration) -> Result<usize, TransportError>;
+
+    /// Discards every pending byte in both directions.
+    fn flush(&mut self) -> Result<(), TransportError>;
+}
+
+impl Transport for Uart {
+    fn write(&mut self, frame: &[u8]) -> Result<usize, TransportError> {
+        Ok(Uart::write(self, frame)?)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], timeout: Duration) -> Result<usize, TransportError> {
+        // Blocking until a minimum length arrives would hang forever on a silent board,
+        // so keep reading whatever arrives until the buffer is full or the line goes quiet
+        self.set_read_mode(0, timeout)?;
+
+        let mut read = 0;
+
+        while read < buffer.len() {
+            match Uart::read(self, &mut buffer[read..])? {
+                0 => break,
+                len => read += len,
+            }
+        }
+
+        Ok(read)
+    }
+
+    fn flush(&mut self) -> Result<(), TransportError> {
+        Ok(Uart::flush(self, Queue::Both)?)
+    }
+}
+
+type Responder = Box<dyn FnMut(&[u8]) -> Vec<u8> + Send>;
+
+/// In-memory transport: every written frame is handed to a responder whose reply is queued to be read back.
+pub struct MemoryTransport {
+    responder: Responder,
+    pending: VecDeque<u8>,
+    written: Vec<Vec<u8>>,
+}
+
+impl MemoryTransport {
+    pub fn new(responder: impl FnMut(&[u8]) -> Vec<u8> + Send + 'static) -> Self {
+        MemoryTransport {
+            responder: Box::new(responder),
+            pending: VecDeque::new(),
+            written: Vec::new(),
+        }
+    }
+
+    /// Every frame written so far, in order.
+    pub fn written(&self) -> &[Vec<u8>] {
+        &self.written
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn write(&mut self, frame: &[u8]) -> Result<usize, TransportError> {
+        self.written.push(frame.to_vec());
+        self.pending.extend((self.responder)(frame));
+
+        Ok(frame.len())
+    }
+
+    fn read(&mut self, buffer: &mut [u8], _timeout: Duration) -> Result<usize, TransportError> {
+        let len = buffer.len().min(self.pending.len());
+
+        for (byte, value) in buffer.iter_mut().zip(self.pending.drain(..len)) {
+            *byte = value;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), TransportError> {
+        self.pending.clear();
+
+        Ok(())
+    }
+}